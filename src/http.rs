@@ -1,14 +1,18 @@
 use crate::controller::InternalCommand;
+use crate::server::db::SearchFilter;
 use crate::utils::traits::{Runnable, Service};
 use crate::{InternalChannelsManager, WSChannelsManager};
 use crossbeam_channel::Receiver;
 use log::info;
+use serde::Deserialize;
 use serde_json::json;
 use std::fs;
+use std::io::Read;
 use std::str::FromStr;
 use std::thread::JoinHandle;
 use std::{io::Error, thread};
 use tiny_http::{Header, Method, Request, Response, StatusCode};
+use url::form_urlencoded;
 use wg_2024::config::Server;
 use wg_2024::network::NodeId;
 
@@ -60,6 +64,13 @@ impl Service for HttpServer {
 
 type ResponseType = Response<std::io::Cursor<Vec<u8>>>;
 
+/// Body of an announcement request
+#[derive(Debug, Deserialize)]
+struct AnnounceRequest {
+    message: String,
+    recipients: Option<Vec<NodeId>>,
+}
+
 impl HttpServer {
     pub fn new(
         address: String,
@@ -74,17 +85,26 @@ impl HttpServer {
     }
 
     fn handle_request(&self, mut req: Request) -> Result<(), Error> {
-        let method = req.method();
-        let url = req.url();
+        let method = req.method().clone();
+        let url = req.url().to_string();
         info!("[SERVER-HTTP] @GET Received request: {method} {url}");
 
-        let response = match (method, url) {
+        let response = match (&method, url.as_str()) {
             // @ GET method
             // Description: serve index.html as root file
             (Method::Get, "/") => self.handle_main(),
             // @Get Method
             // Description: apis for retrieving some server information
-            (Method::Get, path) if path.starts_with("/api/servers") => self.handle_server_apis(url),
+            (Method::Get, path) if path.starts_with("/api/servers") => {
+                self.handle_server_apis(path)
+            }
+            // @Post Method
+            // Description: send an announcement to the users of a server
+            (Method::Post, path) if path.starts_with("/api/servers/announce/") => {
+                let mut body = String::new();
+                req.as_reader().read_to_string(&mut body)?;
+                self.handle_announce(path, &body)
+            }
             // @Get Method
             // Description: serve static content
             (Method::Get, path) if path.starts_with('/') => self.handle_static_files(path),
@@ -103,6 +123,8 @@ impl HttpServer {
     }
 
     fn handle_server_apis(&self, url: &str) -> ResponseType {
+        // Split the query string from the path
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
         let parts: Vec<&str> = url.split('/').collect();
 
         // Check the URL
@@ -142,6 +164,63 @@ impl HttpServer {
                 Response::from_string(json_response)
                     .with_header(Header::from_str("Content-Type: application/json").unwrap())
                     .with_header(Header::from_str("Access-Control-Allow-Origin: *").unwrap())
+            } else if parts[3] == "rooms" {
+                // URL matches the pattern "/api/servers/rooms/:server_id"
+                // Fetch server rooms based on server_id
+                WSChannelsManager::get_server_rooms(server_id);
+                let json_response =
+                    json!({ "status": "success", "message": "Waiting for rooms..." }).to_string();
+
+                Response::from_string(json_response)
+                    .with_header(Header::from_str("Content-Type: application/json").unwrap())
+                    .with_header(Header::from_str("Access-Control-Allow-Origin: *").unwrap())
+            } else if parts[3] == "search" {
+                // URL matches the pattern "/api/servers/search/:server_id?q=...&src=...&dest=...&since=...&until=...&limit=..."
+                // Search the server messages based on server_id and wait for the results
+                let (text, filter) = Self::parse_search_query(query);
+                let json_response =
+                    match WSChannelsManager::search_server_messages(server_id, text, filter) {
+                        Some(Ok(results)) => json!({ "status": "success", "results": results }),
+                        Some(Err(err)) => json!({ "status": "failure", "message": err }),
+                        None => json!({ "status": "failure", "message": "Search not available" }),
+                    }
+                    .to_string();
+
+                Response::from_string(json_response)
+                    .with_header(Header::from_str("Content-Type: application/json").unwrap())
+                    .with_header(Header::from_str("Access-Control-Allow-Origin: *").unwrap())
+            } else if parts[3] == "topology" {
+                // URL matches the pattern "/api/servers/topology/:server_id[?format=dot]"
+                // Fetch the topology learned by the server, as JSON with its DOT rendering or as DOT only
+                let topology = WSChannelsManager::get_server_topology(server_id);
+                let dot_only = form_urlencoded::parse(query.as_bytes())
+                    .any(|(key, value)| key == "format" && value == "dot");
+                match topology {
+                    Some(topology) if dot_only => Response::from_string(topology.to_dot())
+                        .with_header(Header::from_str("Content-Type: text/vnd.graphviz").unwrap())
+                        .with_header(Header::from_str("Access-Control-Allow-Origin: *").unwrap()),
+                    topology => {
+                        let json_response = match topology {
+                            Some(topology) => json!({
+                                "status": "success",
+                                "topology": topology,
+                                "dot": topology.to_dot(),
+                            }),
+                            None => {
+                                json!({ "status": "failure", "message": "Topology not available" })
+                            }
+                        }
+                        .to_string();
+
+                        Response::from_string(json_response)
+                            .with_header(
+                                Header::from_str("Content-Type: application/json").unwrap(),
+                            )
+                            .with_header(
+                                Header::from_str("Access-Control-Allow-Origin: *").unwrap(),
+                            )
+                    }
+                }
             } else {
                 // URL doesn't match anything of the above
                 let json_response =
@@ -166,6 +245,60 @@ impl HttpServer {
         }
     }
 
+    /// Sends an announcement to the users of a server.
+    ///
+    /// URL matches the pattern "/api/servers/announce/:server_id", the body is a JSON object
+    /// `{ "message": "...", "recipients": [1, 2] }` where `recipients` is optional and defaults to every active user.
+    fn handle_announce(&self, url: &str, body: &str) -> ResponseType {
+        let parts: Vec<&str> = url.split('/').collect();
+        let server_id = parts.get(4).and_then(|id| id.parse::<NodeId>().ok());
+        let request = serde_json::from_str::<AnnounceRequest>(body);
+
+        let (status_code, json_response) = match (server_id, request) {
+            (Some(server_id), Ok(request)) if !request.message.trim().is_empty() => {
+                match WSChannelsManager::announce(server_id, request.message, request.recipients) {
+                    Some(recipients) => (
+                        200,
+                        json!({ "status": "success", "recipients": recipients }),
+                    ),
+                    None => (
+                        404,
+                        json!({ "status": "failure", "message": "Server not available" }),
+                    ),
+                }
+            }
+            _ => (
+                400,
+                json!({ "status": "failure", "message": "Wrong announcement provided" }),
+            ),
+        };
+
+        Response::from_string(json_response.to_string())
+            .with_status_code(status_code)
+            .with_header(Header::from_str("Content-Type: application/json").unwrap())
+            .with_header(Header::from_str("Access-Control-Allow-Origin: *").unwrap())
+    }
+
+    /// Extracts the searched text and the filters from the query string of a search request.
+    /// Parameters that are missing or cannot be parsed are ignored.
+    fn parse_search_query(query: &str) -> (String, SearchFilter) {
+        let mut text = String::new();
+        let mut filter = SearchFilter::default();
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "q" => text = value.into_owned(),
+                "src" => filter.src_id = value.parse().ok(),
+                "dest" => filter.dest_id = value.parse().ok(),
+                "since" => filter.since = value.parse().ok(),
+                "until" => filter.until = value.parse().ok(),
+                "limit" => filter.limit = value.parse().ok(),
+                _ => {}
+            }
+        }
+        (text, filter)
+    }
+
     fn handle_static_files(&self, path: &str) -> ResponseType {
         let sanitized_path = String::from(&path[1..]); // Remove initial slash '/'
         match fs::read(format!("{}/{}", self.public_path, sanitized_path)) {
@@ -8,6 +8,12 @@ pub mod utils;
 mod websocket;
 
 pub use controller::RustBustersServerController;
+pub use server::ad::codec::Encoding;
+pub use server::builder::RustBustersServerBuilder;
+pub use server::config::{
+    DbLocation, DiscoveryConfig, FecConfig, MailboxConfig, OutboundQueueConfig, PresenceConfig,
+    ReassemblyConfig, ReplicationConfig, RetransmissionConfig, RoutingPolicy, TopologyConfig,
+};
 pub use server::network_listener::RustBustersServer;
 pub use state::InternalChannelsManager;
 pub use state::StatsManager;
@@ -1,64 +1,357 @@
-use crate::RustBustersServer;
-use common_utils::HostMessage;
+use crate::server::ad::{codec, fec};
+use crate::server::peer::NetworkPayload;
+use crate::{RustBustersServer, StatsManager};
+use log::warn;
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+/// Time a completed session is remembered, to ignore its fragments received again.
+const COMPLETED_SESSION_RETENTION: Duration = Duration::from_secs(5 * 60);
+
+/// The fragments received so far for a session being reassembled.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingSession {
+    pub(crate) fragments: Vec<Option<Fragment>>,
+    pub(crate) received: u64,
+    pub(crate) last_update: Instant,
+}
+
+impl PendingSession {
+    fn new(total_n_fragments: u64) -> Self {
+        Self {
+            fragments: vec![None; total_n_fragments as usize],
+            received: 0,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Bytes allocated for the fragments of the session
+    pub(crate) fn buffered_bytes(&self) -> usize {
+        buffered_bytes(self.fragments.len() as u64)
+    }
+}
+
+fn buffered_bytes(total_n_fragments: u64) -> usize {
+    (total_n_fragments as usize).saturating_mul(FRAGMENT_DSIZE)
+}
 
 impl RustBustersServer {
-    /// Reassembles message fragments into a complete `HostMessage` using the session's fragments.
+    /// Reassembles message fragments into a complete `NetworkPayload` using the session's fragments.
     ///
     /// ### Parameters
+    /// - `source: NodeId` – The host that sent the session.
     /// - `session_id: u64` – The identifier of the session for which fragments need to be reassembled.
     ///
     /// ### Returns
-    /// - `Result<HostMessage, String>`:
-    ///   - `Ok(HostMessage)` if the fragments are successfully reassembled and deserialized into a valid `HostMessage`.
+    /// - `Result<NetworkPayload, String>`:
+    ///   - `Ok(NetworkPayload)` if the fragments are successfully reassembled and deserialized into a valid `NetworkPayload`.
     ///   - `Err(String)` containing an error message if any issue occurs during reassembly or deserialization.
     ///
     /// ### Behavior
-    /// - Retrieves the fragments associated with the given `session_id` from the `pending_received` map.
-    /// - Attempts to concatenate the byte data from each fragment into a complete byte array.
-    /// - If all fragments are successfully concatenated:
-    ///   - Trims the byte array up to the first null byte (0) to determine the message's actual length.
-    ///   - Attempts to convert the byte array into a UTF-8 string.
-    ///   - If successful, it deserializes the string into a `HostMessage` using `serde_json`.
-    /// - Returns an error if any fragment is missing, concatenation fails, UTF-8 conversion fails, or deserialization fails.
-    pub(crate) fn reassemble_fragments(&mut self, session_id: u64) -> Result<HostMessage, String> {
-        match self.pending_received.remove(&session_id) {
-            None => Err(format!("No fragments for session {}", session_id)),
-            Some(fragments) => {
+    /// - Retrieves the fragments associated with `(source, session_id)` from the `pending_received` map:
+    ///   every host picks its own session ids, so the same id sent by two hosts belongs to two sessions.
+    /// - Attempts to concatenate the first `length` bytes of each fragment into a complete byte array.
+    /// - If all fragments are successfully concatenated, decodes the byte array with `codec::decode()`,
+    ///   which detects whether the message is binary or JSON encoded.
+    /// - Fragments with forward error correction are decoded with `fec::decode()` instead, rebuilding
+    ///   the missing data fragments from the parity ones, which is counted in the stats.
+    /// - Returns an error if any fragment is missing, concatenation fails, or decoding fails.
+    pub(crate) fn reassemble_fragments(
+        &mut self,
+        source: NodeId,
+        session_id: u64,
+    ) -> Result<NetworkPayload, String> {
+        match self.pending_received.remove(&(source, session_id)) {
+            None => Err(format!(
+                "No fragments for session {} from {}",
+                session_id, source
+            )),
+            Some(session) => {
+                self.buffered_bytes -= session.buffered_bytes();
+                let fragments = session.fragments;
+                let fec_fragment = fragments.iter().flatten().find(|f| fec::is_fec_fragment(f));
+                if let Some(fragment) = fec_fragment {
+                    let data_fragments = fec::data_fragments(fragment) as usize;
+                    return match fec::decode(data_fragments, fragments) {
+                        Some((byte_array, recovered)) => {
+                            if recovered {
+                                StatsManager::inc_messages_recovered(self.id);
+                            }
+                            codec::decode(&byte_array)
+                        }
+                        None => Err("Not enough fragments to recover the message".to_string()),
+                    };
+                }
+
                 let concatenated: Result<Vec<u8>, &str> =
                     fragments
-                        .0
                         .into_iter()
                         .try_fold(Vec::new(), |mut acc, f| match f {
                             Some(fragment) => {
-                                acc.extend_from_slice(&fragment.data);
+                                let length = (fragment.length as usize).min(FRAGMENT_DSIZE);
+                                acc.extend_from_slice(&fragment.data[..length]);
                                 Ok(acc)
                             }
                             None => Err("Missing fragment"),
                         });
 
-                if let Ok(byte_array) = concatenated {
-                    // Find the actual string length (till the first 0)
-                    let len = byte_array
-                        .iter()
-                        .position(|&x| x == 0)
-                        .unwrap_or(byte_array.len());
-
-                    // Converti l'array di byte in una stringa
-                    let serialized_str = std::str::from_utf8(&byte_array[..len]);
-                    let serialized_str = match serialized_str {
-                        Ok(s) => s,
-                        Err(_) => return Err("Error in JSON string conversion".to_string()),
-                    };
-
-                    if let Ok(msg) = serde_json::from_str(serialized_str) {
-                        Ok(msg)
-                    } else {
-                        Err("Error in deserialization".to_string())
-                    }
-                } else {
-                    Err("Error in reassembly".to_string())
+                match concatenated {
+                    Ok(byte_array) => codec::decode(&byte_array),
+                    Err(_) => Err("Error in reassembly".to_string()),
                 }
             }
         }
     }
+
+    /// Creates the reassembly buffer of a new session, making room for it within the configured limits.
+    ///
+    /// ### Parameters
+    /// - `source: NodeId` – The host that sent the session.
+    /// - `session_id: u64` – The identifier of the session.
+    /// - `total_n_fragments: u64` – The number of fragments announced by the first received fragment.
+    ///
+    /// ### Returns
+    /// - `Ok(())` if the buffer was created.
+    /// - `Err(String)` if the number of fragments is zero, larger than `max_fragments`, or needs more
+    ///   than `max_buffered_bytes_per_source` on its own, or if the buffers of all the sources are full.
+    ///
+    /// ### Behavior
+    /// While `source` already has `max_sessions_per_source` sessions, or its buffered bytes would exceed
+    /// `max_buffered_bytes_per_source`, its least recently updated session is discarded. The sessions of
+    /// the other sources are never discarded: if the bytes buffered for all the sources would still exceed
+    /// `max_buffered_bytes`, the new session is refused.
+    pub(crate) fn open_pending_session(
+        &mut self,
+        source: NodeId,
+        session_id: u64,
+        total_n_fragments: u64,
+    ) -> Result<(), String> {
+        if total_n_fragments == 0 || total_n_fragments > self.reassembly.max_fragments {
+            return Err(format!(
+                "Refusing session {} from {} with {} fragments",
+                session_id, source, total_n_fragments
+            ));
+        }
+        let bytes = buffered_bytes(total_n_fragments);
+        let max_source_bytes = self
+            .reassembly
+            .max_buffered_bytes_per_source
+            .min(self.reassembly.max_buffered_bytes);
+        if bytes > max_source_bytes {
+            return Err(format!(
+                "Refusing session {} from {} needing {} bytes",
+                session_id, source, bytes
+            ));
+        }
+
+        while self.source_pending_sessions(source) >= self.reassembly.max_sessions_per_source.max(1)
+        {
+            let Some(oldest) = self.oldest_pending_session(source) else {
+                break;
+            };
+            self.discard_pending_session(oldest, "too many sessions from the same source");
+        }
+        while self.source_buffered_bytes(source) + bytes > max_source_bytes {
+            let Some(oldest) = self.oldest_pending_session(source) else {
+                break;
+            };
+            self.discard_pending_session(oldest, "too many bytes buffered for the same source");
+        }
+        if self.buffered_bytes + bytes > self.reassembly.max_buffered_bytes {
+            return Err(format!(
+                "Refusing session {} from {}: reassembly buffers full",
+                session_id, source
+            ));
+        }
+
+        self.buffered_bytes += bytes;
+        self.pending_received
+            .insert((source, session_id), PendingSession::new(total_n_fragments));
+        Ok(())
+    }
+
+    /// Remembers that a session was completed, so that its fragments received again are not reassembled twice.
+    pub(crate) fn record_completed_session(&mut self, source: NodeId, session_id: u64) {
+        self.completed_sessions
+            .insert((source, session_id), Instant::now());
+    }
+
+    /// Returns whether the session was completed recently.
+    pub(crate) fn is_completed_session(&self, source: NodeId, session_id: u64) -> bool {
+        self.completed_sessions.contains_key(&(source, session_id))
+    }
+
+    /// Discards the sessions that did not receive any fragment for `session_timeout` and forgets
+    /// the sessions completed long ago.
+    pub(crate) fn expire_pending_sessions(&mut self) {
+        self.completed_sessions
+            .retain(|_, completed_at| completed_at.elapsed() < COMPLETED_SESSION_RETENTION);
+
+        let expired: Vec<(NodeId, u64)> = self
+            .pending_received
+            .iter()
+            .filter(|(_, session)| session.last_update.elapsed() >= self.reassembly.session_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            self.discard_pending_session(key, "timed out");
+        }
+    }
+
+    /// Returns the least recently updated session of a source.
+    fn oldest_pending_session(&self, source: NodeId) -> Option<(NodeId, u64)> {
+        self.pending_received
+            .iter()
+            .filter(|((session_source, _), _)| *session_source == source)
+            .min_by_key(|(_, session)| session.last_update)
+            .map(|(key, _)| *key)
+    }
+
+    /// Returns the number of sessions of a source being reassembled.
+    fn source_pending_sessions(&self, source: NodeId) -> usize {
+        self.pending_received
+            .keys()
+            .filter(|(session_source, _)| *session_source == source)
+            .count()
+    }
+
+    /// Returns the bytes allocated for the sessions of a source being reassembled.
+    fn source_buffered_bytes(&self, source: NodeId) -> usize {
+        self.pending_received
+            .iter()
+            .filter(|((session_source, _), _)| *session_source == source)
+            .map(|(_, session)| session.buffered_bytes())
+            .sum()
+    }
+
+    fn discard_pending_session(&mut self, key: (NodeId, u64), reason: &str) {
+        if let Some(session) = self.pending_received.remove(&key) {
+            self.buffered_bytes -= session.buffered_bytes();
+            let (source, session_id) = key;
+            warn!(
+                "Server {}: Discarding session {} from {} with {} of {} fragments: {}",
+                self.id,
+                session_id,
+                source,
+                session.received,
+                session.fragments.len(),
+                reason
+            );
+            StatsManager::inc_sessions_discarded(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ad::codec::Encoding;
+    use crate::ReassemblyConfig;
+    use common_utils::{ClientToServerMessage, HostMessage};
+
+    /// Splits the bytes into fragments whose unused bytes hold garbage instead of zeros
+    fn garbage_padded_fragments(bytes: &[u8]) -> Vec<Fragment> {
+        let chunks: Vec<&[u8]> = bytes.chunks(FRAGMENT_DSIZE).collect();
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut data = [b'}'; FRAGMENT_DSIZE];
+                data[..chunk.len()].copy_from_slice(chunk);
+                Fragment {
+                    fragment_index: i as u64,
+                    total_n_fragments: chunks.len() as u64,
+                    length: chunk.len() as u8,
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn honours_fragment_length() {
+        let mut server = RustBustersServer::for_tests(1);
+        let payload = NetworkPayload::Host(HostMessage::FromClient(
+            ClientToServerMessage::RegisterUser {
+                name: "a name long enough to need more than one fragment ".repeat(4),
+            },
+        ));
+        for encoding in [Encoding::Json, Encoding::Binary] {
+            let (bytes, _) = codec::encode(&payload, encoding, false);
+            let fragments = garbage_padded_fragments(&bytes);
+            assert!(fragments.len() > 1);
+
+            server
+                .open_pending_session(2, 5, fragments.len() as u64)
+                .unwrap();
+            let session = server.pending_received.get_mut(&(2, 5)).unwrap();
+            session.received = fragments.len() as u64;
+            session.fragments = fragments.into_iter().map(Some).collect();
+
+            let reassembled = server.reassemble_fragments(2, 5).unwrap();
+            assert_eq!(
+                serde_json::to_value(&reassembled).unwrap(),
+                serde_json::to_value(&payload).unwrap()
+            );
+            assert_eq!(server.buffered_bytes, 0);
+        }
+    }
+
+    #[test]
+    fn does_not_discard_sessions_of_other_sources() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.reassembly = ReassemblyConfig {
+            max_fragments: 20,
+            max_sessions_per_source: 4,
+            max_buffered_bytes_per_source: 10 * FRAGMENT_DSIZE,
+            max_buffered_bytes: 15 * FRAGMENT_DSIZE,
+            ..ReassemblyConfig::default()
+        };
+
+        server.open_pending_session(2, 1, 5).unwrap();
+        // The sessions of source 3 are limited to its own share of the buffers
+        for session_id in 0..4 {
+            server.open_pending_session(3, session_id, 5).unwrap();
+        }
+        assert!(server.pending_received.contains_key(&(2, 1)));
+        assert_eq!(server.source_buffered_bytes(3), 10 * FRAGMENT_DSIZE);
+
+        // With the buffers full, a new source is refused instead of discarding the others
+        assert!(server.open_pending_session(4, 1, 5).is_err());
+        assert!(server.pending_received.contains_key(&(2, 1)));
+        assert_eq!(server.source_pending_sessions(3), 2);
+        assert_eq!(server.buffered_bytes, 15 * FRAGMENT_DSIZE);
+
+        // A session larger than the share of a source is refused
+        assert!(server.open_pending_session(2, 2, 11).is_err());
+    }
+
+    #[test]
+    fn fails_on_missing_fragment() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.open_pending_session(2, 5, 3).unwrap();
+        assert!(server.reassemble_fragments(2, 5).is_err());
+        assert!(server.pending_received.is_empty());
+    }
+
+    #[test]
+    fn discards_sessions_without_fragments_for_the_timeout() {
+        let mut server = RustBustersServer::for_tests(37);
+        server.reassembly.session_timeout = Duration::from_secs(1);
+        server.open_pending_session(2, 1, 3).unwrap();
+        server.open_pending_session(3, 1, 2).unwrap();
+        server
+            .pending_received
+            .get_mut(&(2, 1))
+            .unwrap()
+            .last_update = Instant::now() - Duration::from_secs(2);
+
+        server.expire_pending_sessions();
+        assert!(!server.pending_received.contains_key(&(2, 1)));
+        assert!(server.pending_received.contains_key(&(3, 1)));
+        assert_eq!(server.buffered_bytes, 2 * FRAGMENT_DSIZE);
+        let stats = serde_json::to_value(StatsManager::get_stats(37)).unwrap();
+        assert_eq!(stats["sessionsDiscarded"], 1);
+    }
 }
@@ -0,0 +1,287 @@
+use crate::server::peer::{NetworkPayload, PeerMessage};
+use common_utils::HostMessage;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use wg_2024::packet::FRAGMENT_DSIZE;
+
+/// First bytes of a binary encoded message. JSON text never starts with them.
+const MAGIC: [u8; 2] = [0xB5, 0x52];
+/// Version of the binary framing
+const VERSION: u8 = 1;
+/// Length of the binary header: magic, version and flags
+const HEADER_LEN: usize = MAGIC.len() + 2;
+/// Flag set when the bincode payload is compressed with deflate
+const FLAG_DEFLATE: u8 = 0x01;
+/// Flags understood by this version
+const KNOWN_FLAGS: u8 = FLAG_DEFLATE;
+
+/// Encoding of the messages carried by the fragments of a session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// `serde_json` text, understood by every host of the network.
+    #[default]
+    Json,
+    /// `bincode` with a versioned header, only understood by the servers.
+    ///
+    /// Layout: `MAGIC (2 bytes) | VERSION (1 byte) | flags (1 byte) | bincode payload`.
+    /// With `FLAG_DEFLATE` the bincode payload is compressed with deflate.
+    Binary,
+}
+
+/// Tagged form of `NetworkPayload` used by the binary encoding, which cannot decode untagged enums.
+/// Both enums must keep the same variants in the same order.
+#[derive(Serialize)]
+enum BinaryPayloadRef<'a> {
+    Host(&'a HostMessage),
+    Server(&'a PeerMessage),
+}
+
+#[derive(Deserialize)]
+enum BinaryPayload {
+    Host(HostMessage),
+    Server(PeerMessage),
+}
+
+/// Encodes a message into the bytes to be split into fragments.
+///
+/// ### Parameters
+/// - `payload: &NetworkPayload` – The message.
+/// - `encoding: Encoding` – The encoding of the message.
+/// - `compress: bool` – Whether a binary encoded message can be compressed. The compressed form is only
+///   used when it takes fewer fragments than the uncompressed one.
+///
+/// ### Returns
+/// The encoded bytes and the number of fragments saved by the compression.
+pub(crate) fn encode(
+    payload: &NetworkPayload,
+    encoding: Encoding,
+    compress: bool,
+) -> (Vec<u8>, u64) {
+    match encoding {
+        Encoding::Json => (
+            serde_json::to_vec(payload).expect("Should be serializable"),
+            0,
+        ),
+        Encoding::Binary => {
+            let tagged = match payload {
+                NetworkPayload::Host(message) => BinaryPayloadRef::Host(message),
+                NetworkPayload::Server(message) => BinaryPayloadRef::Server(message),
+            };
+            let body = bincode::serialize(&tagged).expect("Should be serializable");
+            let fragments = fragment_count(HEADER_LEN + body.len());
+
+            if compress && fragments > 1 {
+                if let Some(compressed) = deflate(&body) {
+                    let compressed_fragments = fragment_count(HEADER_LEN + compressed.len());
+                    if compressed_fragments < fragments {
+                        return (
+                            frame(FLAG_DEFLATE, &compressed),
+                            fragments - compressed_fragments,
+                        );
+                    }
+                }
+            }
+            (frame(0, &body), 0)
+        }
+    }
+}
+
+/// Decodes the bytes of a reassembled message, detecting its encoding.
+///
+/// JSON messages sent by hosts that fill the unused bytes of the last fragment with zeros are accepted:
+/// trailing zeros are ignored, since JSON text never contains a raw NUL byte.
+pub(crate) fn decode(bytes: &[u8]) -> Result<NetworkPayload, String> {
+    if bytes.starts_with(&MAGIC) {
+        return decode_binary(bytes);
+    }
+
+    let len = bytes
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1);
+    let serialized_str = std::str::from_utf8(&bytes[..len])
+        .map_err(|_| "Error in JSON string conversion".to_string())?;
+    serde_json::from_str(serialized_str).map_err(|_| "Error in deserialization".to_string())
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<NetworkPayload, String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("Truncated binary header".to_string());
+    }
+
+    let version = bytes[MAGIC.len()];
+    if version != VERSION {
+        return Err(format!("Unsupported binary encoding version {version}"));
+    }
+    let flags = bytes[MAGIC.len() + 1];
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(format!("Unsupported binary encoding flags {flags:#04x}"));
+    }
+
+    let body = if flags & FLAG_DEFLATE != 0 {
+        inflate(&bytes[HEADER_LEN..])?
+    } else {
+        bytes[HEADER_LEN..].to_vec()
+    };
+
+    match bincode::deserialize(&body) {
+        Ok(BinaryPayload::Host(message)) => Ok(NetworkPayload::Host(message)),
+        Ok(BinaryPayload::Server(message)) => Ok(NetworkPayload::Server(message)),
+        Err(err) => Err(format!("Error in binary deserialization: {err}")),
+    }
+}
+
+/// Prepends the binary header to an encoded payload
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    bytes.push(flags);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Number of fragments needed to carry `len` bytes
+fn fragment_count(len: usize) -> u64 {
+    ((len + FRAGMENT_DSIZE - 1) / FRAGMENT_DSIZE) as u64
+}
+
+fn deflate(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).ok()?;
+    encoder.finish().ok()
+}
+
+fn inflate(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(bytes)
+        .read_to_end(&mut decompressed)
+        .map_err(|err| format!("Error in decompression: {err}"))?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_utils::{ClientToServerMessage, MessageBody, MessageContent};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn host_payload(text: &str) -> NetworkPayload {
+        NetworkPayload::Host(HostMessage::FromClient(
+            ClientToServerMessage::SendPrivateMessage {
+                recipient_id: 7,
+                message: MessageBody {
+                    sender_id: 3,
+                    content: MessageContent::Text(text.to_string()),
+                    timestamp: "1700000000".to_string(),
+                },
+            },
+        ))
+    }
+
+    fn random_body(rng: &mut StdRng) -> MessageBody {
+        let content = if rng.random_bool(0.8) {
+            MessageContent::Text(random_text(rng))
+        } else {
+            MessageContent::Image(random_text(rng))
+        };
+        MessageBody {
+            sender_id: rng.random(),
+            content,
+            timestamp: rng.random::<u32>().to_string(),
+        }
+    }
+
+    /// Returns a text mixing NUL bytes, characters escaped by JSON and arbitrary Unicode
+    fn random_text(rng: &mut StdRng) -> String {
+        let len = rng.random_range(0..400);
+        (0..len)
+            .map(|_| match rng.random_range(0..4) {
+                0 => ['\0', '"', '\\', '\n', '}'][rng.random_range(0..5)],
+                1 => rng.random::<char>(),
+                _ => rng.random_range('a'..='z'),
+            })
+            .collect()
+    }
+
+    fn random_payload(rng: &mut StdRng) -> NetworkPayload {
+        if rng.random_bool(0.5) {
+            NetworkPayload::Host(HostMessage::FromClient(
+                ClientToServerMessage::SendPrivateMessage {
+                    recipient_id: rng.random(),
+                    message: random_body(rng),
+                },
+            ))
+        } else {
+            let visited_len = rng.random_range(1..5);
+            NetworkPayload::Server(PeerMessage::Relay {
+                relay_id: random_text(rng),
+                origin_server: rng.random(),
+                message_id: random_text(rng),
+                sender_id: rng.random(),
+                recipient_id: rng.random(),
+                message: random_body(rng),
+                visited: (0..visited_len).map(|_| rng.random()).collect(),
+            })
+        }
+    }
+
+    fn assert_round_trip(payload: &NetworkPayload, encoding: Encoding, compress: bool) {
+        let (bytes, _) = encode(payload, encoding, compress);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_random_messages() {
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..300 {
+            let payload = random_payload(&mut rng);
+            for encoding in [Encoding::Json, Encoding::Binary] {
+                for compress in [false, true] {
+                    assert_round_trip(&payload, encoding, compress);
+                }
+            }
+
+            // Clients keep receiving the JSON of plain HostMessages
+            if let NetworkPayload::Host(message) = &payload {
+                let (bytes, _) = encode(&payload, Encoding::Json, true);
+                assert_eq!(bytes, serde_json::to_vec(message).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn compresses_repetitive_binary_messages() {
+        let payload = host_payload(&"repeated ".repeat(200));
+        let (bytes, saved) = encode(&payload, Encoding::Binary, true);
+        assert!(saved > 0);
+        assert_eq!(bytes[MAGIC.len() + 1], FLAG_DEFLATE);
+    }
+
+    #[test]
+    fn decodes_nul_padded_json() {
+        let payload = host_payload("padded");
+        let (mut bytes, _) = encode(&payload, Encoding::Json, false);
+        let padded_len = bytes.len() + FRAGMENT_DSIZE - bytes.len() % FRAGMENT_DSIZE;
+        bytes.resize(padded_len, 0);
+        assert!(decode(&bytes).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_binary_versions_and_flags() {
+        let (mut bytes, _) = encode(&host_payload("hello"), Encoding::Binary, false);
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(decode(&bytes).is_err());
+        bytes[MAGIC.len()] = VERSION;
+        bytes[MAGIC.len() + 1] = 0x80;
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -1,12 +1,41 @@
-use crate::RustBustersServer;
-use common_utils::HostMessage;
-use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+use crate::server::ad::codec::{self, Encoding};
+use crate::server::ad::fec;
+use crate::server::peer::NetworkPayload;
+use crate::{RustBustersServer, StatsManager};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Fragment, NodeType, FRAGMENT_DSIZE};
 
 impl RustBustersServer {
-    /// Splits a `HostMessage` into multiple fragments for transmission.
+    /// Returns the encoding of the sessions toward a destination: the configured `peer_encoding` toward
+    /// the servers that advertised binary decoding in their directory, JSON toward every other host.
+    pub(crate) fn encoding_for(&self, destination_id: NodeId) -> Encoding {
+        match self.known_node_types.get(&destination_id) {
+            Some(NodeType::Server) if self.binary_peers.contains(&destination_id) => {
+                self.peer_encoding
+            }
+            _ => Encoding::Json,
+        }
+    }
+
+    /// Returns the estimated loss rate of the route toward a destination when its sessions need
+    /// forward error correction: it must be enabled, the destination must be a server, and the loss
+    /// rate must reach `min_loss_rate`.
+    pub(crate) fn fec_loss_rate(&self, destination_id: NodeId, route: &[NodeId]) -> Option<f64> {
+        if !self.fec.enabled
+            || self.known_node_types.get(&destination_id) != Some(&NodeType::Server)
+        {
+            return None;
+        }
+        let loss_rate = self.route_loss_rate(route);
+        (loss_rate >= self.fec.min_loss_rate).then_some(loss_rate)
+    }
+
+    /// Splits a `NetworkPayload` into multiple fragments for transmission.
     ///
     /// # Parameters
-    /// - `message: &HostMessage` – The message to be fragmented.
+    /// - `message: &NetworkPayload` – The message to be fragmented.
+    /// - `encoding: Encoding` – The encoding of the message bytes.
+    /// - `loss_rate: Option<f64>` – The loss rate of the route when parity fragments must be added, see `fec_loss_rate()`.
     ///
     /// # Returns
     /// - `Vec<Fragment>`: A vector containing all the fragments of the message. Each fragment is a `Fragment` structure containing:
@@ -16,15 +45,34 @@ impl RustBustersServer {
     ///   - `data`: The chunk of data in the fragment, padded to the size of `FRAGMENT_DSIZE` if necessary.
     ///
     /// # Behavior
-    /// - Serializes the provided `HostMessage` into a JSON string and converts it to bytes.
+    /// - Encodes the provided `NetworkPayload` into bytes with `codec::encode()`, compressing it when
+    ///   `compression` is enabled and it saves fragments, and records the saved fragments in the stats.
     /// - Breaks the byte data into chunks, each of size `FRAGMENT_DSIZE` or less.
     /// - Creates a `Fragment` for each chunk, which includes:
     ///   - The fragment's index and the total number of fragments.
     ///   - The chunk's data and its length.
     /// - Collects all fragments and returns them as a vector.
-    pub(crate) fn disassemble_message(&self, message: &HostMessage) -> Vec<Fragment> {
-        let serialized_str = serde_json::to_string(&message).unwrap();
-        let bytes = serialized_str.as_bytes();
+    /// - With a `loss_rate`, splits the bytes with `fec::encode()` instead, adding the parity fragments
+    ///   needed for that loss rate: every fragment then has length `FEC_MARKER`.
+    pub(crate) fn disassemble_message(
+        &self,
+        message: &NetworkPayload,
+        encoding: Encoding,
+        loss_rate: Option<f64>,
+    ) -> Vec<Fragment> {
+        let (bytes, fragments_saved) = codec::encode(message, encoding, self.compression);
+        if fragments_saved > 0 {
+            StatsManager::inc_messages_compressed(self.id);
+            StatsManager::add_fragments_saved(self.id, fragments_saved);
+        }
+
+        if let Some(loss_rate) = loss_rate {
+            let data_fragments = (bytes.len() + fec::SHARD_SIZE - 1) / fec::SHARD_SIZE;
+            let parity = fec::parity_fragments(data_fragments, loss_rate, self.fec.max_redundancy);
+            if parity > 0 {
+                return fec::encode(&bytes, parity);
+            }
+        }
 
         // Fragment the data into chunks of FRAGMENT_DSIZE bytes
         let total_size = bytes.len();
@@ -0,0 +1,401 @@
+use crate::{RustBustersServer, StatsManager};
+use log::info;
+use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+/// `length` of the fragments of a session with forward error correction, never used by plain fragments.
+///
+/// Their first data byte holds the number of data fragments of the session, the rest holds a shard.
+pub(crate) const FEC_MARKER: u8 = 0xFF;
+/// Bytes of a message carried by a fragment with forward error correction
+pub(crate) const SHARD_SIZE: usize = FRAGMENT_DSIZE - 1;
+/// Maximum number of fragments, data and parity, of a session with forward error correction
+const MAX_FEC_FRAGMENTS: usize = 255;
+
+/// Irreducible polynomial of GF(256): x^8 + x^4 + x^3 + x^2 + 1
+const GF_POLY: u16 = 0x11d;
+const GF_TABLES: ([u8; 512], [u8; 256]) = gf_tables();
+const GF_EXP: [u8; 512] = GF_TABLES.0;
+const GF_LOG: [u8; 256] = GF_TABLES.1;
+
+/// A sent session with forward error correction, complete once `data_fragments` of its fragments are acked.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FecSession {
+    pub(crate) data_fragments: u64,
+    pub(crate) total_fragments: u64,
+    pub(crate) acked: u64,
+    pub(crate) dropped: u64,
+}
+
+impl FecSession {
+    pub(crate) fn is_complete(&self) -> bool {
+        self.acked >= self.data_fragments
+    }
+}
+
+/// Returns whether the fragment belongs to a session with forward error correction.
+pub(crate) fn is_fec_fragment(fragment: &Fragment) -> bool {
+    fragment.length == FEC_MARKER
+}
+
+/// Returns the number of data fragments of the session of a fragment with forward error correction.
+pub(crate) fn data_fragments(fragment: &Fragment) -> u64 {
+    u64::from(fragment.data[0])
+}
+
+/// Returns the number of parity fragments to add to `data_fragments` fragments sent along a route
+/// that drops `loss_rate` of them, or zero when the session would be too large.
+///
+/// Enough parity fragments are added for the expected number of delivered fragments to exceed the
+/// data fragments by one, up to `max_redundancy` times the data fragments. The computation is done
+/// in floating point, so that a loss rate close to or above one cannot overflow.
+pub(crate) fn parity_fragments(
+    data_fragments: usize,
+    loss_rate: f64,
+    max_redundancy: f64,
+) -> usize {
+    let max_parity = MAX_FEC_FRAGMENTS.saturating_sub(data_fragments);
+    if data_fragments == 0 || max_parity == 0 {
+        return 0;
+    }
+
+    let k = data_fragments as f64;
+    let needed = if loss_rate < 1.0 {
+        (k / (1.0 - loss_rate)).ceil() + 1.0 - k
+    } else {
+        f64::INFINITY
+    };
+    let allowed = (k * max_redundancy).ceil();
+    let parity = needed.min(allowed).max(1.0);
+    if parity > max_parity as f64 {
+        0
+    } else {
+        parity as usize
+    }
+}
+
+/// Splits the bytes of a message into `SHARD_SIZE` data fragments followed by `parity` parity fragments.
+///
+/// The last shard is padded with zeros, which the decoders of `codec` ignore.
+pub(crate) fn encode(bytes: &[u8], parity: usize) -> Vec<Fragment> {
+    let data_shards: Vec<Vec<u8>> = bytes
+        .chunks(SHARD_SIZE)
+        .map(|chunk| {
+            let mut shard = chunk.to_vec();
+            shard.resize(SHARD_SIZE, 0);
+            shard
+        })
+        .collect();
+    let k = data_shards.len();
+    let parity_shards: Vec<Vec<u8>> = (0..parity)
+        .map(|row| {
+            let mut shard = vec![0u8; SHARD_SIZE];
+            for (col, data) in data_shards.iter().enumerate() {
+                let coefficient = cauchy(k, row, col);
+                for (byte, &value) in shard.iter_mut().zip(data) {
+                    *byte ^= gf_mul(coefficient, value);
+                }
+            }
+            shard
+        })
+        .collect();
+
+    let total_n_fragments = (k + parity) as u64;
+    data_shards
+        .into_iter()
+        .chain(parity_shards)
+        .enumerate()
+        .map(|(i, shard)| {
+            let mut data = [0u8; FRAGMENT_DSIZE];
+            data[0] = k as u8;
+            data[1..].copy_from_slice(&shard);
+            Fragment {
+                fragment_index: i as u64,
+                total_n_fragments,
+                length: FEC_MARKER,
+                data,
+            }
+        })
+        .collect()
+}
+
+/// Rebuilds the bytes of a message from the fragments received for its session.
+///
+/// ### Parameters
+/// - `data_fragments: usize` – The number of data fragments of the session.
+/// - `fragments: Vec<Option<Fragment>>` – The fragments of the session, indexed by `fragment_index`.
+///
+/// ### Returns
+/// - `Some((bytes, recovered))` with the concatenated data shards, `recovered` being set when some
+///   of them had to be rebuilt from the parity fragments.
+/// - `None` if fewer than `data_fragments` fragments were received.
+pub(crate) fn decode(
+    data_fragments: usize,
+    fragments: Vec<Option<Fragment>>,
+) -> Option<(Vec<u8>, bool)> {
+    let k = data_fragments;
+    let shards: Vec<(usize, Vec<u8>)> = fragments
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, fragment)| fragment.map(|f| (i, f.data[1..].to_vec())))
+        .take(k)
+        .collect();
+    if k == 0 || shards.len() < k {
+        return None;
+    }
+    if shards.iter().enumerate().all(|(i, (index, _))| i == *index) {
+        return Some((
+            shards.into_iter().flat_map(|(_, shard)| shard).collect(),
+            false,
+        ));
+    }
+
+    // Rows of the encoding matrix that produced the received shards
+    let matrix = shards
+        .iter()
+        .map(|(index, _)| {
+            (0..k)
+                .map(|col| match index.checked_sub(k) {
+                    None => u8::from(*index == col),
+                    Some(row) => cauchy(k, row, col),
+                })
+                .collect()
+        })
+        .collect();
+    let inverse = invert(matrix)?;
+
+    let mut bytes = Vec::with_capacity(k * SHARD_SIZE);
+    for coefficients in inverse {
+        let mut shard = vec![0u8; SHARD_SIZE];
+        for (coefficient, (_, received)) in coefficients.into_iter().zip(&shards) {
+            for (byte, &value) in shard.iter_mut().zip(received) {
+                *byte ^= gf_mul(coefficient, value);
+            }
+        }
+        bytes.extend(shard);
+    }
+    Some((bytes, true))
+}
+
+impl RustBustersServer {
+    /// Handles a dropped fragment of a session with forward error correction.
+    ///
+    /// ### Returns
+    /// `true` if the fragments still on their way are enough to rebuild the message, in which case the dropped
+    /// fragment is given up instead of being sent again; `false` if it must be sent again as usual.
+    pub(crate) fn absorb_fec_drop(&mut self, session_id: u64, fragment_index: u64) -> bool {
+        let Some(fec) = self.fec_sessions.get_mut(&session_id) else {
+            return false;
+        };
+        if fec.total_fragments - fec.dropped - 1 < fec.data_fragments {
+            return false;
+        }
+        fec.dropped += 1;
+
+        info!(
+            "Server {}: Fragment {} of session {} dropped, covered by the parity fragments",
+            self.id, fragment_index, session_id
+        );
+        self.pending_sent.remove(&(session_id, fragment_index));
+        self.retransmission_timers
+            .remove(&(session_id, fragment_index));
+        StatsManager::inc_fec_drops_absorbed(self.id);
+        true
+    }
+}
+
+/// Coefficient of the Cauchy matrix for a parity `row` and a data `col`: `1 / (x_row + y_col)`,
+/// with `x_row = k + row` and `y_col = col` all distinct, so that any `k` rows of the encoding matrix
+/// (the identity followed by the Cauchy rows) are invertible.
+fn cauchy(k: usize, row: usize, col: usize) -> u8 {
+    gf_inv(((k + row) as u8) ^ (col as u8))
+}
+
+/// Inverts a square matrix over GF(256) with the Gauss-Jordan elimination.
+fn invert(mut matrix: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..n)
+        .map(|i| (0..n).map(|j| u8::from(i == j)).collect())
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n).find(|&row| matrix[row][col] != 0)?;
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+
+        let factor = gf_inv(matrix[col][col]);
+        for j in 0..n {
+            matrix[col][j] = gf_mul(matrix[col][j], factor);
+            inverse[col][j] = gf_mul(inverse[col][j], factor);
+        }
+        for row in 0..n {
+            let factor = matrix[row][col];
+            if row == col || factor == 0 {
+                continue;
+            }
+            for j in 0..n {
+                let (value, inverse_value) = (matrix[col][j], inverse[col][j]);
+                matrix[row][j] ^= gf_mul(factor, value);
+                inverse[row][j] ^= gf_mul(factor, inverse_value);
+            }
+        }
+    }
+    Some(inverse)
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GF_EXP[GF_LOG[a as usize] as usize + GF_LOG[b as usize] as usize]
+}
+
+fn gf_inv(a: u8) -> u8 {
+    GF_EXP[255 - GF_LOG[a as usize] as usize]
+}
+
+/// Exponential and logarithm tables of GF(256), the exponentials repeated to skip the modulo in `gf_mul`.
+const fn gf_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= GF_POLY;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ad::codec::Encoding;
+    use common_utils::{ClientToServerMessage, HostMessage};
+    use wg_2024::network::{NodeId, SourceRoutingHeader};
+    use wg_2024::packet::{NackType, NodeType, Packet, PacketType};
+
+    /// Returns a stat of a server by its serialized name
+    fn stat(server_id: NodeId, name: &str) -> u64 {
+        let stats = serde_json::to_value(StatsManager::get_stats(server_id)).unwrap();
+        stats[name].as_u64().unwrap()
+    }
+
+    #[test]
+    fn sizes_parity_within_bounds() {
+        assert_eq!(parity_fragments(10, 0.0, 1.0), 1);
+        assert_eq!(parity_fragments(10, 0.5, 1.0), 10);
+        assert_eq!(parity_fragments(10, 0.5, 0.2), 2);
+        assert_eq!(parity_fragments(0, 0.5, 1.0), 0);
+        assert_eq!(parity_fragments(255, 0.1, 1.0), 0);
+        assert_eq!(parity_fragments(200, 0.5, 1.0), 0);
+    }
+
+    #[test]
+    fn does_not_overflow_on_extreme_loss_rates() {
+        for loss_rate in [0.999_999, 1.0, 2.0, -1.0, f64::NAN, f64::INFINITY] {
+            for max_redundancy in [0.0, 1.0, f64::INFINITY, f64::NAN] {
+                assert!(parity_fragments(10, loss_rate, max_redundancy) <= MAX_FEC_FRAGMENTS - 10);
+            }
+        }
+        assert_eq!(parity_fragments(10, 1.0, 1.0), 10);
+    }
+
+    #[test]
+    fn recovers_lost_data_fragments() {
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let fragments = encode(&bytes, 4);
+        let k = data_fragments(&fragments[0]) as usize;
+        assert_eq!(fragments.len(), k + 4);
+
+        // Lose the first and third data fragments and one parity fragment
+        let received: Vec<Option<Fragment>> = fragments
+            .into_iter()
+            .enumerate()
+            .map(|(i, fragment)| (![0, 2, k + 1].contains(&i)).then_some(fragment))
+            .collect();
+        let (decoded, recovered) = decode(k, received).unwrap();
+        assert!(recovered);
+        assert_eq!(&decoded[..bytes.len()], &bytes[..]);
+    }
+
+    #[test]
+    fn delivers_without_resending_a_dropped_fragment() {
+        // Server 35 reaches server 36 through drone 2
+        let mut sender = RustBustersServer::for_tests(35);
+        let mut receiver = RustBustersServer::for_tests(36);
+        sender.fec.enabled = true;
+        sender.fec.min_loss_rate = 0.0;
+        for (node, node_type) in [
+            (35, NodeType::Server),
+            (2, NodeType::Drone),
+            (36, NodeType::Server),
+        ] {
+            sender.known_node_types.insert(node, node_type);
+        }
+        for (a, b) in [(35, 2), (2, 36)] {
+            sender.topology.entry(a).or_default().push(b);
+            sender.topology.entry(b).or_default().push(a);
+        }
+        let (drone_send, drone_recv) = crossbeam_channel::unbounded();
+        sender.packet_send.insert(2, drone_send);
+
+        let message = HostMessage::FromClient(ClientToServerMessage::RegisterUser {
+            name: "dropped ".repeat(60),
+        });
+        sender.send_encoded_message(36, message, None, Encoding::Json);
+        let packets: Vec<Packet> = drone_recv.try_iter().collect();
+        let session_id = packets[0].session_id;
+        let fec_session = sender.fec_sessions[&session_id];
+        assert!(fec_session.total_fragments > fec_session.data_fragments);
+        assert_eq!(packets.len() as u64, fec_session.total_fragments);
+
+        // The first fragment is dropped by the drone and not sent again
+        sender.handle_nack(session_id, 0, NackType::Dropped, Some(2));
+        assert!(drone_recv.try_recv().is_err());
+        assert!(!sender.pending_sent.contains_key(&(session_id, 0)));
+        assert!(!sender.retransmission_timers.contains_key(&(session_id, 0)));
+        assert_eq!(stat(35, "fecDropsAbsorbed"), 1);
+
+        // The receiver rebuilds the message from the other fragments
+        for (i, packet) in packets.iter().skip(1).enumerate() {
+            let PacketType::MsgFragment(fragment) = packet.pack_type.clone() else {
+                panic!("Not a fragment: {:?}", packet.pack_type);
+            };
+            let delivered = i as u64 + 1 >= fec_session.data_fragments;
+            receiver.handle_fragment(
+                fragment,
+                session_id,
+                SourceRoutingHeader {
+                    hop_index: 2,
+                    hops: vec![35, 2, 36],
+                },
+            );
+            assert_eq!(stat(36, "messagesReceived"), u64::from(delivered));
+        }
+        assert!(receiver.pending_received.is_empty());
+
+        // The session is complete once as many fragments as the data fragments are acked
+        for packet in packets.iter().skip(1) {
+            let PacketType::MsgFragment(fragment) = &packet.pack_type else {
+                unreachable!();
+            };
+            sender.handle_ack(session_id, fragment.fragment_index);
+            if fragment.fragment_index == fec_session.data_fragments {
+                break;
+            }
+        }
+        assert!(!sender.fec_sessions.contains_key(&session_id));
+        assert!(!sender.sessions_info.contains_key(&session_id));
+        assert!(sender.pending_sent.is_empty());
+        assert!(sender.retransmission_timers.is_empty());
+    }
+}
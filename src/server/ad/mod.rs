@@ -1,2 +1,4 @@
 pub mod assembler;
+pub mod codec;
 pub mod disassembler;
+pub mod fec;
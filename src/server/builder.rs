@@ -0,0 +1,201 @@
+use crate::server::ad::codec::Encoding;
+use crate::server::config::{
+    DbLocation, DiscoveryConfig, FecConfig, MailboxConfig, OutboundQueueConfig, PresenceConfig,
+    ReassemblyConfig, ReplicationConfig, RetransmissionConfig, RoutingPolicy, TopologyConfig,
+};
+use crate::RustBustersServer;
+use common_utils::{HostCommand, HostEvent};
+use crossbeam_channel::{Receiver, Sender};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+/// Builder of a `RustBustersServer`.
+///
+/// The channels toward the simulation controller and the neighbours are required, every other
+/// setting falls back to its default when not given. The database is only opened by `build`.
+pub struct RustBustersServerBuilder {
+    pub(crate) id: NodeId,
+    pub(crate) controller_send: Sender<HostEvent>,
+    pub(crate) controller_recv: Receiver<HostCommand>,
+    pub(crate) packet_send: HashMap<NodeId, Sender<Packet>>,
+    pub(crate) packet_recv: Receiver<Packet>,
+    pub(crate) server_controller_sender: Sender<HostCommand>,
+
+    pub(crate) discovery_interval: Duration,
+    pub(crate) discovery: DiscoveryConfig,
+    pub(crate) db_location: DbLocation,
+    pub(crate) routing_policy: RoutingPolicy,
+    pub(crate) retransmission: RetransmissionConfig,
+    pub(crate) outbound_queue: OutboundQueueConfig,
+    pub(crate) mailbox: MailboxConfig,
+    pub(crate) presence: PresenceConfig,
+    pub(crate) replication: ReplicationConfig,
+    pub(crate) peer_encoding: Encoding,
+    pub(crate) compression: bool,
+    pub(crate) fec: FecConfig,
+    pub(crate) reassembly: ReassemblyConfig,
+    pub(crate) topology: TopologyConfig,
+}
+
+impl RustBustersServerBuilder {
+    pub fn new(
+        id: NodeId,
+        controller_send: Sender<HostEvent>,
+        controller_recv: Receiver<HostCommand>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+        packet_recv: Receiver<Packet>,
+        server_controller_sender: Sender<HostCommand>,
+    ) -> Self {
+        Self {
+            id,
+            controller_send,
+            controller_recv,
+            packet_send,
+            packet_recv,
+            server_controller_sender,
+            discovery_interval: Duration::from_secs(30),
+            discovery: DiscoveryConfig::default(),
+            db_location: DbLocation::default(),
+            routing_policy: RoutingPolicy::default(),
+            retransmission: RetransmissionConfig::default(),
+            outbound_queue: OutboundQueueConfig::default(),
+            mailbox: MailboxConfig::default(),
+            presence: PresenceConfig::default(),
+            replication: ReplicationConfig::default(),
+            peer_encoding: Encoding::Binary,
+            compression: true,
+            fec: FecConfig::default(),
+            reassembly: ReassemblyConfig::default(),
+            topology: TopologyConfig::default(),
+        }
+    }
+
+    /// Sets the interval at which the network discovery is repeated.
+    pub fn discovery_interval(mut self, discovery_interval: Duration) -> Self {
+        self.discovery_interval = discovery_interval;
+        self
+    }
+
+    /// Sets how far the discovery interval backs off and how often a discovery can be launched.
+    pub fn discovery_config(mut self, discovery: DiscoveryConfig) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    /// Sets where the database of the server is stored.
+    pub fn db_location(mut self, db_location: DbLocation) -> Self {
+        self.db_location = db_location;
+        self
+    }
+
+    /// Stores the database as `server_<id>.db` inside `dir`.
+    pub fn db_directory(self, dir: impl Into<PathBuf>) -> Self {
+        self.db_location(DbLocation::Directory(dir.into()))
+    }
+
+    /// Stores the database in the file at `path`.
+    pub fn db_path(self, path: impl Into<PathBuf>) -> Self {
+        self.db_location(DbLocation::Path(path.into()))
+    }
+
+    /// Keeps the database in memory, it is discarded when the server stops.
+    pub fn in_memory_db(self) -> Self {
+        self.db_location(DbLocation::InMemory)
+    }
+
+    /// Sets the policy used to choose the route toward a destination.
+    pub fn routing_policy(mut self, routing_policy: RoutingPolicy) -> Self {
+        self.routing_policy = routing_policy;
+        self
+    }
+
+    /// Sets the retransmission policy used for unacknowledged fragments.
+    pub fn retransmission_config(mut self, retransmission: RetransmissionConfig) -> Self {
+        self.retransmission = retransmission;
+        self
+    }
+
+    /// Sets the limits of the queue holding the messages toward unreachable destinations.
+    pub fn outbound_queue_config(mut self, outbound_queue: OutboundQueueConfig) -> Self {
+        self.outbound_queue = outbound_queue;
+        self
+    }
+
+    /// Sets the limits of the mailbox storing the messages for users that are not registered.
+    pub fn mailbox_config(mut self, mailbox: MailboxConfig) -> Self {
+        self.mailbox = mailbox;
+        self
+    }
+
+    /// Sets when the users that stopped talking to the server are unregistered.
+    pub fn presence_config(mut self, presence: PresenceConfig) -> Self {
+        self.presence = presence;
+        self
+    }
+
+    /// Sets whether the stored messages are replicated to the other servers.
+    pub fn replication_config(mut self, replication: ReplicationConfig) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    /// Sets the encoding of the messages sent to the other servers that advertised binary decoding,
+    /// clients and the other servers always receive JSON.
+    pub fn peer_encoding(mut self, peer_encoding: Encoding) -> Self {
+        self.peer_encoding = peer_encoding;
+        self
+    }
+
+    /// Sets whether the binary messages sent to the other servers are compressed
+    /// when it reduces the number of fragments.
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets whether parity fragments are added to the sessions toward the other servers over lossy routes.
+    pub fn fec_config(mut self, fec: FecConfig) -> Self {
+        self.fec = fec;
+        self
+    }
+
+    /// Sets the limits of the buffers holding the fragments of the received sessions.
+    pub fn reassembly_config(mut self, reassembly: ReassemblyConfig) -> Self {
+        self.reassembly = reassembly;
+        self
+    }
+
+    /// Sets after how many discovery rounds an unconfirmed link is removed from the topology.
+    pub fn topology_config(mut self, topology: TopologyConfig) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Opens the database and creates the server.
+    pub fn build(self) -> RustBustersServer {
+        RustBustersServer::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+impl RustBustersServer {
+    /// Creates a server with an in-memory database and no neighbours, for the unit tests
+    pub(crate) fn for_tests(id: NodeId) -> Self {
+        let (controller_send, _) = crossbeam_channel::unbounded();
+        let (server_controller_sender, controller_recv) = crossbeam_channel::unbounded();
+        let (_, packet_recv) = crossbeam_channel::unbounded();
+        RustBustersServer::builder(
+            id,
+            controller_send,
+            controller_recv,
+            HashMap::new(),
+            packet_recv,
+            server_controller_sender,
+        )
+        .in_memory_db()
+        .build()
+    }
+}
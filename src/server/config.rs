@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use wg_2024::network::NodeId;
+
+/// Retransmission policy for the fragments waiting to be acknowledged in `pending_sent`.
+///
+/// Every sent fragment is armed with a timer that starts at `rto` and doubles on each
+/// retransmission (exponential backoff), capped at `max_rto`. Once a fragment has been
+/// retransmitted `max_retries` times without being acked, the whole session is dropped.
+#[derive(Debug, Clone, Copy)]
+pub struct RetransmissionConfig {
+    pub rto: Duration,
+    pub max_rto: Duration,
+    pub max_retries: u32,
+}
+
+impl Default for RetransmissionConfig {
+    fn default() -> Self {
+        Self {
+            rto: Duration::from_millis(1000),
+            max_rto: Duration::from_secs(8),
+            max_retries: 5,
+        }
+    }
+}
+
+impl RetransmissionConfig {
+    /// Returns the timeout to wait after the `retries`-th retransmission of a fragment.
+    pub(crate) fn timeout(&self, retries: u32) -> Duration {
+        self.rto
+            .checked_mul(1 << retries.min(16))
+            .unwrap_or(self.max_rto)
+            .min(self.max_rto)
+    }
+}
+
+/// Policy used by `find_route` to choose among the routes toward a destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoutingPolicy {
+    /// Shortest route by number of hops (BFS).
+    #[default]
+    HopCount,
+    /// Route with the lowest expected transmission count (ETX), computed with Dijkstra from the
+    /// drop rate observed on every drone.
+    ExpectedTransmissions,
+}
+
+/// Limits of the queue holding the messages toward destinations without a known route.
+///
+/// At most `capacity` messages are parked for every destination: when the queue is full the oldest
+/// message is dropped. A message still parked after `deadline` is dropped and reported as failed.
+#[derive(Debug, Clone, Copy)]
+pub struct OutboundQueueConfig {
+    pub capacity: usize,
+    pub deadline: Duration,
+}
+
+impl Default for OutboundQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 32,
+            deadline: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Limits of the mailbox storing the private messages sent to clients that are not registered.
+///
+/// A recipient holds at most `capacity` pending messages, further messages are refused.
+/// Messages older than `retention` are removed from the database, whether delivered or not.
+#[derive(Debug, Clone, Copy)]
+pub struct MailboxConfig {
+    pub capacity: usize,
+    pub retention: Duration,
+}
+
+impl Default for MailboxConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 50,
+            retention: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Expiry of the registered users that stopped talking to the server.
+///
+/// A user that sent no message for `idle_timeout` is unregistered, as is a user to which
+/// `max_delivery_failures` consecutive sessions could not be delivered.
+#[derive(Debug, Clone, Copy)]
+pub struct PresenceConfig {
+    pub idle_timeout: Duration,
+    pub max_delivery_failures: u32,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(5 * 60),
+            max_delivery_failures: 3,
+        }
+    }
+}
+
+/// Replication of the stored messages to the other servers of the network.
+///
+/// When `enabled`, every message saved in the database is sent to the servers found by the network
+/// discovery. Servers found later, and servers that missed some messages while unreachable, are
+/// brought up to date in batches of `batch_size` messages.
+#[derive(Debug, Clone, Copy)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+    pub batch_size: usize,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            batch_size: 100,
+        }
+    }
+}
+
+/// Adaptive scheduling of the network discovery.
+///
+/// The discovery starts every `discovery_interval` (see the builder). Every round that does not change the
+/// topology doubles the interval, up to `max_interval`; a round that changes it resets the interval.
+/// Routing errors and changes of the neighbors request an early discovery, but two discoveries are never
+/// launched less than `min_gap` apart.
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveryConfig {
+    pub max_interval: Duration,
+    pub min_gap: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            max_interval: Duration::from_secs(5 * 60),
+            min_gap: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Aging of the topology learned by the network discovery.
+///
+/// Every network discovery is a round, identified by the flood id of its FloodRequest. A link that is not
+/// confirmed by the FloodResponses of `edge_expiry_rounds` rounds is removed from the topology.
+#[derive(Debug, Clone, Copy)]
+pub struct TopologyConfig {
+    pub edge_expiry_rounds: u64,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> Self {
+        Self {
+            edge_expiry_rounds: 3,
+        }
+    }
+}
+
+/// Limits of the buffers holding the fragments of the sessions being reassembled.
+///
+/// A session announcing more than `max_fragments` fragments is refused. A source can have at most
+/// `max_sessions_per_source` sessions being reassembled, buffering at most `max_buffered_bytes_per_source`
+/// bytes: when one of these limits is reached the least recently updated session of that source is discarded.
+/// A new session that would bring the bytes buffered for all the sources over `max_buffered_bytes` is
+/// refused, so that a source cannot discard the sessions of the others.
+/// A session that receives no fragment for `session_timeout` is discarded.
+#[derive(Debug, Clone, Copy)]
+pub struct ReassemblyConfig {
+    pub max_fragments: u64,
+    pub max_sessions_per_source: usize,
+    pub max_buffered_bytes_per_source: usize,
+    pub max_buffered_bytes: usize,
+    pub session_timeout: Duration,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            max_fragments: 8192,
+            max_sessions_per_source: 16,
+            max_buffered_bytes_per_source: 4 * 1024 * 1024,
+            max_buffered_bytes: 16 * 1024 * 1024,
+            session_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Forward error correction of the sessions toward the other servers.
+///
+/// When `enabled`, parity fragments are added to the sessions whose route drops more than
+/// `min_loss_rate` of the fragments, as estimated from the observed reliability of its drones.
+/// The number of parity fragments grows with the loss rate, up to `max_redundancy` times the
+/// number of data fragments. The receiver rebuilds the message from any set of fragments as large
+/// as the data fragments, so a dropped fragment does not need to be sent again.
+#[derive(Debug, Clone, Copy)]
+pub struct FecConfig {
+    pub enabled: bool,
+    pub min_loss_rate: f64,
+    pub max_redundancy: f64,
+}
+
+impl Default for FecConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_loss_rate: 0.05,
+            max_redundancy: 1.0,
+        }
+    }
+}
+
+/// Where the database of a server is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbLocation {
+    /// A `server_<id>.db` file inside the given directory.
+    Directory(PathBuf),
+    /// The given database file.
+    Path(PathBuf),
+    /// A private in-memory database, discarded when the server stops.
+    InMemory,
+}
+
+impl Default for DbLocation {
+    /// `server_<id>.db` in the current working directory.
+    fn default() -> Self {
+        DbLocation::Directory(PathBuf::from("."))
+    }
+}
+
+impl DbLocation {
+    /// Returns the path of the database file of server `server_id`, `None` for an in-memory database.
+    pub(crate) fn path(&self, server_id: NodeId) -> Option<PathBuf> {
+        match self {
+            DbLocation::Directory(dir) => Some(dir.join(format!("server_{}.db", server_id))),
+            DbLocation::Path(path) => Some(path.clone()),
+            DbLocation::InMemory => None,
+        }
+    }
+}
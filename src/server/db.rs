@@ -1,10 +1,91 @@
 use chrono::Utc;
+use common_utils::{MessageBody, MessageContent};
 use log::{info, warn};
-use rusqlite::{params, Connection, Result};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+use rusqlite::ToSql;
+use rusqlite::{params, Connection, Result, Row};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+
+use super::config::DbLocation;
+use super::migrations::migrate;
 use wg_2024::network::NodeId;
 
+/// Delivery state of a private message, updated as its session progresses
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    Queued, // waiting for a route toward the recipient
+    Sent,   // fragments sent, waiting for the Acks
+    Acked,  // every fragment acked by the recipient
+    Failed, // dropped after exhausting the retries or the deadline
+}
+
+impl DeliveryState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryState::Queued => "queued",
+            DeliveryState::Sent => "sent",
+            DeliveryState::Acked => "acked",
+            DeliveryState::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql for DeliveryState {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DeliveryState {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "queued" => Ok(DeliveryState::Queued),
+            "sent" => Ok(DeliveryState::Sent),
+            "acked" => Ok(DeliveryState::Acked),
+            "failed" => Ok(DeliveryState::Failed),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Kind of content of a private message, needed to rebuild its `MessageContent`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    Text,
+    Image,
+}
+
+impl MessageKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::Text => "text",
+            MessageKind::Image => "image",
+        }
+    }
+}
+
+impl ToSql for MessageKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for MessageKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "text" => Ok(MessageKind::Text),
+            "image" => Ok(MessageKind::Image),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Columns of the `messages` table, in the order expected by `DbMessage::from_row`
+const MESSAGE_COLUMNS: &str = "id, src_id, dest_id, message, timestamp, delivery_state, kind";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DbMessage {
@@ -13,10 +94,12 @@ pub struct DbMessage {
     dest_id: NodeId,
     message: String,
     timestamp: i64, // Unix timestamp
+    delivery_state: DeliveryState,
+    kind: MessageKind,
 }
 
 impl DbMessage {
-    pub fn new(src_id: NodeId, dest_id: NodeId, message: String) -> Self {
+    pub fn new(src_id: NodeId, dest_id: NodeId, message: String, kind: MessageKind) -> Self {
         let id = Uuid::new_v4().to_string(); // Generate a new UUID
         let timestamp = Utc::now().timestamp(); // Get current Unix timestamp
 
@@ -26,10 +109,134 @@ impl DbMessage {
             dest_id,
             message,
             timestamp,
+            delivery_state: DeliveryState::Queued,
+            kind,
+        }
+    }
+
+    /// Builds the local copy of a message stored by another server under the id `id`.
+    ///
+    /// The timestamp of the body is kept when it is a valid Unix timestamp.
+    pub fn from_body(id: String, src_id: NodeId, dest_id: NodeId, body: &MessageBody) -> Self {
+        let (message, kind) = match &body.content {
+            MessageContent::Text(text) => (text.clone(), MessageKind::Text),
+            MessageContent::Image(image) => (image.clone(), MessageKind::Image),
+            _ => ("".to_string(), MessageKind::Text),
+        };
+
+        DbMessage {
+            id,
+            src_id,
+            dest_id,
+            message,
+            timestamp: body
+                .timestamp
+                .parse()
+                .unwrap_or_else(|_| Utc::now().timestamp()),
+            delivery_state: DeliveryState::Queued,
+            kind,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// Rebuilds the body of the message as it is exchanged with the clients
+    pub fn to_message_body(&self) -> MessageBody {
+        MessageBody {
+            sender_id: self.src_id,
+            content: match self.kind {
+                MessageKind::Text => MessageContent::Text(self.message.clone()),
+                MessageKind::Image => MessageContent::Image(self.message.clone()),
+            },
+            timestamp: self.timestamp.to_string(),
         }
     }
+
+    /// Builds a message from a row selecting `MESSAGE_COLUMNS`
+    fn from_row(row: &Row) -> Result<DbMessage> {
+        Ok(DbMessage {
+            id: row.get(0)?,
+            src_id: row.get(1)?,
+            dest_id: row.get(2)?,
+            message: row.get(3)?,
+            timestamp: row.get(4)?,
+            delivery_state: row.get(5)?,
+            kind: row.get(6)?,
+        })
+    }
 }
 
+/// A private message waiting in the mailbox of a recipient that is not registered
+#[derive(Debug, Clone)]
+pub struct OfflineMessage {
+    pub(crate) id: String, // UUID as the primary key
+    pub(crate) src_id: NodeId,
+    pub(crate) dest_id: NodeId,
+    pub(crate) body: MessageBody,
+    pub(crate) timestamp: i64, // Unix timestamp
+}
+
+/// A user of the registry, with the node that owns its name
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRecord {
+    pub(crate) node_id: NodeId,
+    pub(crate) name: String,
+    pub(crate) first_seen: i64, // Unix timestamp
+    pub(crate) last_seen: i64,  // Unix timestamp
+    pub(crate) active: bool,
+}
+
+impl UserRecord {
+    fn from_row(row: &Row) -> Result<UserRecord> {
+        Ok(UserRecord {
+            node_id: row.get(0)?,
+            name: row.get(1)?,
+            first_seen: row.get(2)?,
+            last_seen: row.get(3)?,
+            active: row.get(4)?,
+        })
+    }
+}
+
+/// A group chat room with its members
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Room {
+    pub(crate) name: String,
+    pub(crate) owner_id: NodeId,
+    pub(crate) created_at: i64, // Unix timestamp
+    pub(crate) members: Vec<NodeId>,
+}
+
+/// Optional filters of a full-text search over the stored messages
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    pub src_id: Option<NodeId>,
+    pub dest_id: Option<NodeId>,
+    pub since: Option<i64>, // Unix timestamp, inclusive
+    pub until: Option<i64>, // Unix timestamp, inclusive
+    pub limit: Option<usize>,
+}
+
+/// A message matching a full-text search, with its relevance (lower is better)
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    #[serde(flatten)]
+    message: DbMessage,
+    rank: f64,
+}
+
+/// Maximum number of results returned by a full-text search
+const MAX_SEARCH_RESULTS: usize = 100;
+
 pub struct DbManager {
     id: NodeId,
     name: String,
@@ -37,20 +244,21 @@ pub struct DbManager {
 }
 
 impl DbManager {
-    pub fn new(server_id: NodeId, db_name: String) -> Result<DbManager> {
-        info!("[SERVER-{}] Creating database: {}", server_id, db_name);
-        let conn = Connection::open(&db_name)?;
+    pub fn new(server_id: NodeId, location: &DbLocation) -> Result<DbManager> {
+        let (db_name, mut conn) = match location.path(server_id) {
+            Some(path) => {
+                let db_name = path.display().to_string();
+                info!("[SERVER-{}] Creating database: {}", server_id, db_name);
+                (db_name, Connection::open(path)?)
+            }
+            None => {
+                info!("[SERVER-{}] Creating in-memory database", server_id);
+                (":memory:".to_string(), Connection::open_in_memory()?)
+            }
+        };
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                src_id INTEGER NOT NULL,
-                dest_id INTEGER NOT NULL,
-                message TEXT NOT NULL,
-                timestamp INTEGER NOT NULL
-            )",
-            [],
-        )?;
+        // Bring the schema up to date
+        migrate(&mut conn)?;
 
         Ok(DbManager {
             id: server_id,
@@ -65,35 +273,25 @@ impl DbManager {
         src_id: NodeId,
         dest_id: NodeId,
         message: String,
+        kind: MessageKind,
     ) -> Result<DbMessage, rusqlite::Error> {
-        let db_message = DbMessage::new(src_id, dest_id, message);
+        let db_message = DbMessage::new(src_id, dest_id, message, kind);
         self.conn.execute(
-            "INSERT INTO messages (id, src_id, dest_id, message, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![db_message.id, db_message.src_id, db_message.dest_id, db_message.message, db_message.timestamp],
+            "INSERT INTO messages (id, src_id, dest_id, message, timestamp, delivery_state, kind) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![db_message.id, db_message.src_id, db_message.dest_id, db_message.message, db_message.timestamp, db_message.delivery_state, db_message.kind],
         )?;
         Ok(db_message)
     }
 
     /// Retrieves a message by its ID
     pub fn get(&self, id: Uuid) -> Result<Option<DbMessage>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT src_id, dest_id, message, timestamp FROM messages WHERE id = ?1")?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE id = ?1"
+        ))?;
         let mut rows = stmt.query(params![id.to_string()])?;
 
         if let Some(row) = rows.next()? {
-            let src_id: NodeId = row.get(0)?;
-            let dest_id: NodeId = row.get(1)?;
-            let message: String = row.get(2)?;
-            let timestamp: i64 = row.get(3)?;
-
-            Ok(Some(DbMessage {
-                id: id.to_string(),
-                src_id,
-                dest_id,
-                message,
-                timestamp,
-            }))
+            Ok(Some(DbMessage::from_row(row)?))
         } else {
             Ok(None)
         }
@@ -103,21 +301,8 @@ impl DbManager {
     pub fn get_all(&self) -> Result<Vec<DbMessage>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT id, src_id, dest_id, message, timestamp FROM messages")?;
-        let rows = stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let src_id: NodeId = row.get(1)?;
-            let dest_id: NodeId = row.get(2)?;
-            let message: String = row.get(3)?;
-            let timestamp: i64 = row.get(4)?;
-            Ok(DbMessage {
-                id,
-                src_id,
-                dest_id,
-                message,
-                timestamp,
-            })
-        })?;
+            .prepare(&format!("SELECT {MESSAGE_COLUMNS} FROM messages"))?;
+        let rows = stmt.query_map([], DbMessage::from_row)?;
 
         let mut messages = Vec::new();
         for message in rows {
@@ -126,6 +311,107 @@ impl DbManager {
         Ok(messages)
     }
 
+    /// Retrieves a page of the conversation between two users, in chronological order
+    ///
+    /// The page holds the latest `limit` messages exchanged before the message with id `before`, ordered
+    /// by timestamp and then by insertion, so that messages stored within the same second are neither
+    /// skipped nor repeated across pages. `None` starts from the latest message, an unknown id gives an
+    /// empty page. The returned flag tells whether older messages are available.
+    pub fn get_conversation(
+        &self,
+        user_id: NodeId,
+        peer_id: NodeId,
+        before: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<DbMessage>, bool)> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages
+             WHERE ((src_id = ?1 AND dest_id = ?2) OR (src_id = ?2 AND dest_id = ?1))
+               AND (?3 IS NULL OR (timestamp, rowid) < (SELECT timestamp, rowid FROM messages WHERE id = ?3))
+             ORDER BY timestamp DESC, rowid DESC
+             LIMIT ?4"
+        ))?;
+        // Fetch one more message to know whether there are older ones
+        let rows = stmt.query_map(
+            params![user_id, peer_id, before, (limit + 1) as i64],
+            DbMessage::from_row,
+        )?;
+
+        let mut messages = Vec::new();
+        for message in rows {
+            messages.push(message?);
+        }
+        let has_more = messages.len() > limit;
+        messages.truncate(limit);
+        messages.reverse();
+        Ok((messages, has_more))
+    }
+
+    /// Searches the text messages matching all the words of `query`, most relevant first
+    pub fn search(&self, query: &str, filter: &SearchFilter) -> Result<Vec<SearchResult>> {
+        // Quote every word, so that the FTS5 query syntax cannot be injected
+        let fts_query = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if fts_query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let limit = filter
+            .limit
+            .unwrap_or(MAX_SEARCH_RESULTS)
+            .min(MAX_SEARCH_RESULTS);
+
+        let mut stmt = self.conn.prepare(
+            "SELECT m.id, m.src_id, m.dest_id, m.message, m.timestamp, m.delivery_state, m.kind,
+                    bm25(messages_fts)
+             FROM messages_fts JOIN messages m ON m.rowid = messages_fts.rowid
+             WHERE messages_fts MATCH ?1
+               AND (?2 IS NULL OR m.src_id = ?2)
+               AND (?3 IS NULL OR m.dest_id = ?3)
+               AND (?4 IS NULL OR m.timestamp >= ?4)
+               AND (?5 IS NULL OR m.timestamp <= ?5)
+             ORDER BY bm25(messages_fts)
+             LIMIT ?6",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                fts_query,
+                filter.src_id,
+                filter.dest_id,
+                filter.since,
+                filter.until,
+                limit as i64
+            ],
+            |row| {
+                Ok(SearchResult {
+                    message: DbMessage::from_row(row)?,
+                    rank: row.get(7)?,
+                })
+            },
+        )?;
+
+        let mut results = Vec::new();
+        for result in rows {
+            results.push(result?);
+        }
+        Ok(results)
+    }
+
+    /// Updates the delivery state of a message, returning the updated message
+    pub fn update_delivery_state(
+        &self,
+        id: Uuid,
+        delivery_state: DeliveryState,
+    ) -> Result<Option<DbMessage>> {
+        self.conn.execute(
+            "UPDATE messages SET delivery_state = ?1 WHERE id = ?2",
+            params![delivery_state, id.to_string()],
+        )?;
+        self.get(id)
+    }
+
     /// Removes a message by its ID
     pub fn remove(&self, id: Uuid) -> Result<()> {
         self.conn.execute(
@@ -134,4 +420,324 @@ impl DbManager {
         )?;
         Ok(())
     }
+
+    /// Stores a private message in the mailbox of a recipient that is not registered
+    pub fn insert_offline(
+        &self,
+        src_id: NodeId,
+        dest_id: NodeId,
+        body: &MessageBody,
+    ) -> Result<OfflineMessage> {
+        let serialized_body = serde_json::to_string(body)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let offline_message = OfflineMessage {
+            id: Uuid::new_v4().to_string(),
+            src_id,
+            dest_id,
+            body: body.clone(),
+            timestamp: Utc::now().timestamp(),
+        };
+        self.conn.execute(
+            "INSERT INTO mailbox (id, src_id, dest_id, body, timestamp, status) VALUES (?1, ?2, ?3, ?4, ?5, 'pending')",
+            params![offline_message.id, src_id, dest_id, serialized_body, offline_message.timestamp],
+        )?;
+        Ok(offline_message)
+    }
+
+    /// Counts the messages still pending in the mailbox of a recipient
+    pub fn count_pending_offline(&self, dest_id: NodeId) -> Result<usize> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM mailbox WHERE dest_id = ?1 AND status = 'pending'",
+            params![dest_id],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Retrieves the messages pending in the mailbox of a recipient, oldest first
+    pub fn get_pending_offline(&self, dest_id: NodeId) -> Result<Vec<OfflineMessage>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, src_id, dest_id, body, timestamp FROM mailbox
+             WHERE dest_id = ?1 AND status = 'pending'
+             ORDER BY timestamp, rowid",
+        )?;
+        let rows = stmt.query_map(params![dest_id], |row| {
+            let serialized_body: String = row.get(3)?;
+            let body = serde_json::from_str(&serialized_body).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+            })?;
+            Ok(OfflineMessage {
+                id: row.get(0)?,
+                src_id: row.get(1)?,
+                dest_id: row.get(2)?,
+                body,
+                timestamp: row.get(4)?,
+            })
+        })?;
+
+        let mut messages = Vec::new();
+        for message in rows {
+            messages.push(message?);
+        }
+        Ok(messages)
+    }
+
+    /// Marks a mailbox message as delivered to its recipient
+    pub fn mark_offline_delivered(&self, id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE mailbox SET status = 'delivered' WHERE id = ?1",
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the mailbox messages older than the specified Unix timestamp, returning how many were removed
+    pub fn purge_offline(&self, older_than: i64) -> Result<usize> {
+        self.conn.execute(
+            "DELETE FROM mailbox WHERE timestamp < ?1",
+            params![older_than],
+        )
+    }
+
+    /// Records the registration of a user, reserving its name to `node_id`.
+    /// A node registering with a new name releases its previous one.
+    pub fn register_user(&self, node_id: NodeId, name: &str) -> Result<()> {
+        let now = Utc::now().timestamp();
+        self.conn.execute(
+            "INSERT INTO users (node_id, name, first_seen, last_seen, active) VALUES (?1, ?2, ?3, ?3, 1)
+             ON CONFLICT(node_id) DO UPDATE SET name = excluded.name, last_seen = excluded.last_seen, active = 1",
+            params![node_id, name, now],
+        )?;
+        Ok(())
+    }
+
+    /// Records the unregistration of a user, its name stays reserved
+    pub fn unregister_user(&self, node_id: NodeId) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET active = 0, last_seen = ?1 WHERE node_id = ?2",
+            params![Utc::now().timestamp(), node_id],
+        )?;
+        Ok(())
+    }
+
+    /// Retrieves a user of the registry by its node
+    pub fn get_user(&self, node_id: NodeId) -> Result<Option<UserRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT node_id, name, first_seen, last_seen, active FROM users WHERE node_id = ?1",
+        )?;
+        let mut rows = stmt.query(params![node_id])?;
+
+        if let Some(row) = rows.next()? {
+            Ok(Some(UserRecord::from_row(row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Retrieves a user of the registry by its name
+    pub fn get_user_by_name(&self, name: &str) -> Result<Option<UserRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT node_id, name, first_seen, last_seen, active FROM users WHERE name = ?1",
+        )?;
+        let mut rows = stmt.query(params![name])?;
+
+        if let Some(row) = rows.next()? {
+            Ok(Some(UserRecord::from_row(row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Retrieves the users that were registered when the server last stopped
+    pub fn get_active_users(&self) -> Result<Vec<UserRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT node_id, name, first_seen, last_seen, active FROM users WHERE active = 1",
+        )?;
+        let rows = stmt.query_map([], UserRecord::from_row)?;
+
+        let mut users = Vec::new();
+        for user in rows {
+            users.push(user?);
+        }
+        Ok(users)
+    }
+
+    /// Creates a room owned by `owner_id`, who becomes its first member.
+    /// Returns `false` if a room with the same name already exists.
+    pub fn create_room(&self, name: &str, owner_id: NodeId) -> Result<bool> {
+        let created = self.conn.execute(
+            "INSERT OR IGNORE INTO rooms (name, owner_id, created_at) VALUES (?1, ?2, ?3)",
+            params![name, owner_id, Utc::now().timestamp()],
+        )? > 0;
+        if created {
+            self.add_room_member(name, owner_id)?;
+        }
+        Ok(created)
+    }
+
+    /// Checks whether a room exists
+    pub fn room_exists(&self, name: &str) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM rooms WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Adds a member to a room, returning `false` if it was already a member
+    pub fn add_room_member(&self, room: &str, node_id: NodeId) -> Result<bool> {
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO room_members (room, node_id, joined_at) VALUES (?1, ?2, ?3)",
+            params![room, node_id, Utc::now().timestamp()],
+        )?;
+        Ok(added > 0)
+    }
+
+    /// Removes a member from a room, returning `false` if it was not a member
+    pub fn remove_room_member(&self, room: &str, node_id: NodeId) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM room_members WHERE room = ?1 AND node_id = ?2",
+            params![room, node_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Retrieves the members of a room, in the order they joined
+    pub fn get_room_members(&self, room: &str) -> Result<Vec<NodeId>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT node_id FROM room_members WHERE room = ?1 ORDER BY joined_at, rowid",
+        )?;
+        let rows = stmt.query_map(params![room], |row| row.get(0))?;
+
+        let mut members = Vec::new();
+        for member in rows {
+            members.push(member?);
+        }
+        Ok(members)
+    }
+
+    /// Retrieves all the rooms with their members
+    pub fn get_rooms(&self) -> Result<Vec<Room>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, owner_id, created_at FROM rooms ORDER BY name")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Room {
+                name: row.get(0)?,
+                owner_id: row.get(1)?,
+                created_at: row.get(2)?,
+                members: Vec::new(),
+            })
+        })?;
+
+        let mut rooms = Vec::new();
+        for room in rows {
+            let mut room = room?;
+            room.members = self.get_room_members(&room.name)?;
+            rooms.push(room);
+        }
+        Ok(rooms)
+    }
+
+    /// Inserts a message received from another server, unless a message with the same ID is already stored.
+    /// Returns `true` if the message was inserted.
+    pub fn insert_replica(&self, db_message: &DbMessage) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO messages (id, src_id, dest_id, message, timestamp, delivery_state, kind) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![db_message.id, db_message.src_id, db_message.dest_id, db_message.message, db_message.timestamp, db_message.delivery_state, db_message.kind],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Inserts a message replicated by another server, or brings the delivery state of the stored copy up to
+    /// date with the one of the replica. Returns `true` if the message was inserted or its state changed.
+    pub fn upsert_replica(&self, db_message: &DbMessage) -> Result<bool> {
+        let changed = self.conn.execute(
+            "INSERT INTO messages (id, src_id, dest_id, message, timestamp, delivery_state, kind) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET delivery_state = excluded.delivery_state
+             WHERE delivery_state != excluded.delivery_state",
+            params![db_message.id, db_message.src_id, db_message.dest_id, db_message.message, db_message.timestamp, db_message.delivery_state, db_message.kind],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Retrieves a batch of the messages not older than the specified Unix timestamp, oldest first
+    pub fn get_since(&self, since: i64, offset: usize, limit: usize) -> Result<Vec<DbMessage>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages
+             WHERE timestamp >= ?1
+             ORDER BY timestamp, rowid
+             LIMIT ?2 OFFSET ?3"
+        ))?;
+        let rows = stmt.query_map(
+            params![since, limit as i64, offset as i64],
+            DbMessage::from_row,
+        )?;
+
+        let mut messages = Vec::new();
+        for message in rows {
+            messages.push(message?);
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_through_messages_of_the_same_second() {
+        let db_manager = DbManager::new(1, &DbLocation::InMemory).unwrap();
+        let ids: Vec<String> = (0..5)
+            .map(|i| {
+                let db_message = db_manager
+                    .insert(2, 3, format!("message {i}"), MessageKind::Text)
+                    .unwrap();
+                db_message.id
+            })
+            .collect();
+        db_manager
+            .conn
+            .execute("UPDATE messages SET timestamp = 100", [])
+            .unwrap();
+
+        let mut pages = Vec::new();
+        let mut before: Option<String> = None;
+        loop {
+            let (page, has_more) = db_manager
+                .get_conversation(3, 2, before.as_deref(), 2)
+                .unwrap();
+            before = page.first().map(|message| message.id.clone());
+            pages.push(
+                page.into_iter()
+                    .map(|message| message.id)
+                    .collect::<Vec<_>>(),
+            );
+            if !has_more {
+                break;
+            }
+        }
+
+        assert_eq!(pages.len(), 3);
+        let received: Vec<String> = pages.into_iter().rev().flatten().collect();
+        assert_eq!(received, ids);
+    }
+
+    #[test]
+    fn replicas_follow_the_delivery_state_of_the_origin() {
+        let db_manager = DbManager::new(1, &DbLocation::InMemory).unwrap();
+        let mut db_message = DbMessage::new(2, 3, "hello".to_string(), MessageKind::Text);
+        let id = Uuid::parse_str(&db_message.id).unwrap();
+
+        assert!(db_manager.upsert_replica(&db_message).unwrap());
+        assert!(!db_manager.upsert_replica(&db_message).unwrap());
+
+        db_message.delivery_state = DeliveryState::Acked;
+        assert!(db_manager.upsert_replica(&db_message).unwrap());
+        let stored = db_manager.get(id).unwrap().unwrap();
+        assert_eq!(stored.delivery_state, DeliveryState::Acked);
+        assert_eq!(stored.message, "hello");
+    }
 }
@@ -0,0 +1,254 @@
+use log::info;
+use rusqlite::{ffi, Connection, Result};
+
+/// A schema migration, applied inside its own transaction
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Ordered list of the schema migrations: the database version stored in `PRAGMA user_version`
+/// is the number of migrations already applied.
+///
+/// Migrations must never be modified or reordered once released, new ones are appended.
+/// They are written to be idempotent, since databases created before versioning already contain
+/// some of the changes while reporting version 0.
+const MIGRATIONS: &[Migration] = &[
+    create_messages,
+    create_mailbox,
+    add_delivery_state,
+    add_message_kind,
+    create_messages_fts,
+    create_users,
+    create_rooms,
+];
+
+/// Brings the database schema up to date.
+///
+/// ### Behavior
+/// - Reads the current version from `PRAGMA user_version`.
+/// - Refuses to open a database whose version is newer than the latest known migration.
+/// - Applies the missing migrations in order, each one in a transaction that also bumps the version.
+pub(crate) fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = current_version(conn)?;
+    let latest = MIGRATIONS.len();
+
+    if version > latest {
+        return Err(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CANTOPEN),
+            Some(format!(
+                "database schema version {version} is newer than the supported version {latest}"
+            )),
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+        info!("[DB] Applied migration {}", index + 1);
+    }
+    Ok(())
+}
+
+/// Returns the schema version of the database
+pub(crate) fn current_version(conn: &Connection) -> Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// 1: original schema
+fn create_messages(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            src_id INTEGER NOT NULL,
+            dest_id INTEGER NOT NULL,
+            message TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 2: messages waiting for their recipient to register: status is either 'pending' or 'delivered'
+fn create_mailbox(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mailbox (
+            id TEXT PRIMARY KEY,
+            src_id INTEGER NOT NULL,
+            dest_id INTEGER NOT NULL,
+            body TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending'
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 3: delivery state of the messages, the ones saved before delivery tracking are considered sent
+fn add_delivery_state(conn: &Connection) -> Result<()> {
+    add_column_if_missing(
+        conn,
+        "messages",
+        "delivery_state",
+        "TEXT NOT NULL DEFAULT 'sent'",
+    )
+}
+
+/// 4: kind of content of the messages, and index for the conversation lookups
+fn add_message_kind(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "kind", "TEXT NOT NULL DEFAULT 'text'")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_messages_conversation
+            ON messages (src_id, dest_id, timestamp)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 5: full-text index of the text messages, kept in sync with `messages` by triggers
+fn create_messages_fts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts
+            USING fts5(message, content='messages', content_rowid='rowid');
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages
+        WHEN new.kind = 'text' BEGIN
+            INSERT INTO messages_fts(rowid, message) VALUES (new.rowid, new.message);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages
+        WHEN old.kind = 'text' BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, message) VALUES ('delete', old.rowid, old.message);
+        END;
+
+        INSERT INTO messages_fts(messages_fts) VALUES ('delete-all');
+        INSERT INTO messages_fts(rowid, message)
+            SELECT rowid, message FROM messages WHERE kind = 'text';",
+    )
+}
+
+/// 6: registry of the users that registered at least once, `active` is 1 while they are registered.
+/// Names stay reserved to their node even when the user is not registered.
+fn create_users(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS users (
+            node_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL,
+            active INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 7: group chat rooms and their members, members stay in a room while they are not registered
+fn create_rooms(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS rooms (
+            name TEXT PRIMARY KEY,
+            owner_id INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS room_members (
+            room TEXT NOT NULL REFERENCES rooms (name),
+            node_id INTEGER NOT NULL,
+            joined_at INTEGER NOT NULL,
+            PRIMARY KEY (room, node_id)
+        );",
+    )
+}
+
+/// Adds a column to an existing table, unless the table already has it
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    let mut exists = false;
+    for name in columns {
+        exists |= name? == column;
+    }
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a database with the schema used before versioning, at version 0, holding two messages
+    fn original_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE messages (
+                id TEXT PRIMARY KEY,
+                src_id INTEGER NOT NULL,
+                dest_id INTEGER NOT NULL,
+                message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            INSERT INTO messages VALUES ('a', 1, 2, 'hello world', 100);
+            INSERT INTO messages VALUES ('b', 2, 1, 'goodbye', 101);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn upgrades_original_schema() {
+        let mut conn = original_database();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), MIGRATIONS.len());
+
+        let (delivery_state, kind): (String, String) = conn
+            .query_row(
+                "SELECT delivery_state, kind FROM messages WHERE id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(delivery_state, "sent");
+        assert_eq!(kind, "text");
+
+        // The existing messages are indexed
+        let found: String = conn
+            .query_row(
+                "SELECT m.id FROM messages_fts JOIN messages m ON m.rowid = messages_fts.rowid
+                    WHERE messages_fts MATCH 'hello'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(found, "a");
+
+        // Migrating an up to date database changes nothing
+        migrate(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let mut conn = original_database();
+        conn.pragma_update(None, "user_version", (MIGRATIONS.len() + 1) as i64)
+            .unwrap();
+
+        assert!(migrate(&mut conn).is_err());
+        assert_eq!(current_version(&conn).unwrap(), MIGRATIONS.len() + 1);
+    }
+}
@@ -2,6 +2,10 @@ pub mod network_listener;
 pub mod sc_commands;
 
 pub mod ad;
+pub mod builder;
+pub mod config;
 pub mod db;
+pub mod migrations;
 pub mod network;
 pub mod packet;
+pub mod peer;
@@ -1,16 +1,100 @@
 use common_utils::HostEvent;
 use common_utils::{PacketHeader, PacketTypeHeader};
-use log::{info, warn};
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
 use wg_2024::network::SourceRoutingHeader;
 use wg_2024::packet::NodeType::Server;
 use wg_2024::packet::{FloodRequest, Packet, PacketType};
 
+use crate::server::config::DiscoveryConfig;
 use crate::{RustBustersServer, StatsManager};
 
+/// Decides when the next network discovery is launched, see `DiscoveryConfig`.
+#[derive(Debug, Clone)]
+pub(crate) struct DiscoveryScheduler {
+    config: DiscoveryConfig,
+    base_interval: Duration,
+    interval: Duration,
+    last_discovery: Instant,
+    next_discovery: Instant,
+}
+
+impl DiscoveryScheduler {
+    pub(crate) fn new(base_interval: Duration, config: DiscoveryConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            base_interval,
+            interval: base_interval,
+            last_discovery: now,
+            next_discovery: now + base_interval,
+        }
+    }
+
+    /// Returns the current interval between two discoveries.
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns whether the next discovery should be launched now.
+    pub(crate) fn is_due(&self) -> bool {
+        Instant::now() >= self.next_discovery
+    }
+
+    /// Records a launched discovery, backing off when the previous round did not change the topology.
+    fn record_discovery(&mut self, topology_changed: bool) {
+        self.interval = if topology_changed {
+            self.base_interval
+        } else {
+            self.interval
+                .saturating_mul(2)
+                .min(self.config.max_interval.max(self.base_interval))
+        };
+        self.last_discovery = Instant::now();
+        self.next_discovery = self.last_discovery + self.interval;
+    }
+
+    /// Brings the next discovery forward to the earliest time allowed by `min_gap`.
+    ///
+    /// ### Returns
+    /// `true` if the discovery is delayed by the rate limit.
+    fn request(&mut self) -> bool {
+        self.interval = self.base_interval;
+        let earliest = self.last_discovery + self.config.min_gap;
+        self.next_discovery = self.next_discovery.min(earliest.max(Instant::now()));
+        earliest > Instant::now()
+    }
+}
+
 impl RustBustersServer {
+    /// Asks for a network discovery as soon as the rate limit allows it, e.g. after a routing error
+    /// or a change of the neighbors. Requests received while one is already pending are merged.
+    pub(crate) fn request_discovery(&mut self, reason: &str) {
+        let deferred = self.discovery_scheduler.request();
+        debug!(
+            "Server {}: Network discovery requested ({}){}",
+            self.id,
+            reason,
+            if deferred {
+                ", deferred by the rate limit"
+            } else {
+                ""
+            }
+        );
+        if deferred {
+            StatsManager::inc_discoveries_deferred(self.id);
+        }
+        StatsManager::set_discovery_interval_ms(
+            self.id,
+            self.discovery_scheduler.interval().as_millis() as u64,
+        );
+    }
+
     /// Initiates a network discovery process by broadcasting a `FloodRequest` to all known neighbors.
     ///
     /// ### Behavior
+    /// - Closes the previous discovery round with `close_discovery_round()`, aging the topology, and schedules
+    ///   the next discovery: the interval grows while the topology is stable.
     /// - Increments the internal `flood_id_counter` to generate a unique `flood_id` on every network discovery.
     /// - Creates a `FloodRequest` packet containing:
     ///   - `flood_id`: A unique identifier for the discovery attempt.
@@ -18,7 +102,14 @@ impl RustBustersServer {
     ///   - `path_trace`: A vector tracking the discovery path, initialized with the current server.
     /// - Sends the `FloodRequest` packet to all directly connected neighbors.
     pub fn launch_network_discovery(&mut self) {
-        // Generate a unique flood_id
+        let topology_changed = self.close_discovery_round();
+        self.discovery_scheduler.record_discovery(topology_changed);
+        StatsManager::set_discovery_interval_ms(
+            self.id,
+            self.discovery_scheduler.interval().as_millis() as u64,
+        );
+
+        // Generate a unique flood_id, which identifies the discovery round
         self.flood_id_counter += 1;
         let flood_id = self.flood_id_counter;
 
@@ -63,3 +154,61 @@ impl RustBustersServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(min_gap: Duration) -> DiscoveryScheduler {
+        DiscoveryScheduler::new(
+            Duration::from_secs(10),
+            DiscoveryConfig {
+                max_interval: Duration::from_secs(35),
+                min_gap,
+            },
+        )
+    }
+
+    #[test]
+    fn backs_off_while_the_topology_is_stable() {
+        let mut scheduler = scheduler(Duration::from_secs(2));
+        scheduler.record_discovery(false);
+        assert_eq!(scheduler.interval(), Duration::from_secs(20));
+        scheduler.record_discovery(false);
+        assert_eq!(scheduler.interval(), Duration::from_secs(35));
+        scheduler.record_discovery(false);
+        assert_eq!(scheduler.interval(), Duration::from_secs(35));
+        assert!(!scheduler.is_due());
+
+        // A round that changes the topology resets the interval
+        scheduler.record_discovery(true);
+        assert_eq!(scheduler.interval(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn rate_limits_the_requested_discoveries() {
+        let mut scheduler = scheduler(Duration::from_secs(2));
+        scheduler.record_discovery(false);
+
+        // Right after a discovery, a request waits for `min_gap`
+        assert!(scheduler.request());
+        assert_eq!(scheduler.interval(), Duration::from_secs(10));
+        assert_eq!(
+            scheduler.next_discovery,
+            scheduler.last_discovery + Duration::from_secs(2)
+        );
+        assert!(!scheduler.is_due());
+
+        // Once `min_gap` went by, the discovery is launched at once
+        scheduler.last_discovery -= Duration::from_secs(3);
+        assert!(!scheduler.request());
+        assert!(scheduler.is_due());
+    }
+
+    #[test]
+    fn reports_the_interval_from_the_start() {
+        let _server = RustBustersServer::for_tests(39);
+        let stats = serde_json::to_value(StatsManager::get_stats(39)).unwrap();
+        assert_eq!(stats["discoveryIntervalMs"], 30_000);
+    }
+}
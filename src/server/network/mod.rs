@@ -1,2 +1,4 @@
 pub mod discovery;
+pub mod reliability;
 pub mod router;
+pub mod topology;
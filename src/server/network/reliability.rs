@@ -0,0 +1,214 @@
+use crate::server::config::RoutingPolicy;
+use crate::RustBustersServer;
+use log::debug;
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// Drop rate assumed for a drone that never carried any of our fragments.
+const DEFAULT_DROP_RATE: f64 = 0.1;
+/// Weight given to the latest observation in the moving average of the drop rate.
+const DROP_RATE_SMOOTHING: f64 = 0.1;
+/// Upper bound of the estimated drop rate, so that the ETX of a drone stays finite.
+const MAX_DROP_RATE: f64 = 0.99;
+/// Time after which half of the difference between an estimate and `DEFAULT_DROP_RATE` is forgotten,
+/// so that a drone that is no longer used is eventually tried again.
+const DROP_RATE_HALF_LIFE: Duration = Duration::from_secs(60);
+/// Change of an estimate, since the cached routes were computed, after which they are recomputed.
+const REROUTE_THRESHOLD: f64 = 0.05;
+
+/// Reliability estimate of a drone, learned from the Acks and `Dropped` Nacks of our fragments.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DroneReliability {
+    drop_rate: f64,
+    updated_at: Instant,
+    routed_drop_rate: f64, // estimate used by the cached routes
+}
+
+impl Default for DroneReliability {
+    fn default() -> Self {
+        Self {
+            drop_rate: DEFAULT_DROP_RATE,
+            updated_at: Instant::now(),
+            routed_drop_rate: DEFAULT_DROP_RATE,
+        }
+    }
+}
+
+impl DroneReliability {
+    fn observe(&mut self, dropped: bool) {
+        let sample = if dropped { 1.0 } else { 0.0 };
+        self.drop_rate = ((1.0 - DROP_RATE_SMOOTHING) * self.drop_rate()
+            + DROP_RATE_SMOOTHING * sample)
+            .min(MAX_DROP_RATE);
+        self.updated_at = Instant::now();
+    }
+
+    /// Estimated drop rate, decaying toward `DEFAULT_DROP_RATE` since the last observation.
+    pub(crate) fn drop_rate(&self) -> f64 {
+        let half_lives =
+            self.updated_at.elapsed().as_secs_f64() / DROP_RATE_HALF_LIFE.as_secs_f64();
+        DEFAULT_DROP_RATE + (self.drop_rate - DEFAULT_DROP_RATE) * 0.5_f64.powf(half_lives)
+    }
+
+    /// Whether the estimate moved by at least `REROUTE_THRESHOLD` since the cached routes were computed.
+    fn drifted(&self) -> bool {
+        (self.drop_rate() - self.routed_drop_rate).abs() >= REROUTE_THRESHOLD
+    }
+
+    /// Expected number of transmissions needed to get a fragment through the drone.
+    pub(crate) fn etx(&self) -> f64 {
+        1.0 / (1.0 - self.drop_rate)
+    }
+}
+
+impl RustBustersServer {
+    /// Returns the reliability estimate of the specified drone.
+    pub(crate) fn drone_reliability(&self, drone_id: NodeId) -> DroneReliability {
+        self.drone_reliability
+            .get(&drone_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the estimated probability that a fragment sent along `hops` is dropped by one of its drones.
+    pub(crate) fn route_loss_rate(&self, hops: &[NodeId]) -> f64 {
+        if hops.len() < 3 {
+            return 0.0;
+        }
+        let delivery_rate: f64 = hops[1..hops.len() - 1]
+            .iter()
+            .map(|drone_id| 1.0 - self.drone_reliability(*drone_id).drop_rate())
+            .product();
+        1.0 - delivery_rate
+    }
+
+    /// Records that a fragment travelled along `hops` up to its destination.
+    ///
+    /// Every drone of the route (all the hops except the first and the last one) forwarded it.
+    pub(crate) fn record_delivery(&mut self, hops: &[NodeId]) {
+        if hops.len() < 3 {
+            return;
+        }
+        for drone_id in &hops[1..hops.len() - 1] {
+            self.drone_reliability
+                .entry(*drone_id)
+                .or_default()
+                .observe(false);
+        }
+        self.reevaluate_routes();
+    }
+
+    /// Records that `drone_id` dropped a fragment that was travelling along `hops`.
+    ///
+    /// The drones preceding `drone_id` on the route forwarded the fragment successfully.
+    pub(crate) fn record_drop(&mut self, drone_id: NodeId, hops: &[NodeId]) {
+        for hop in hops.iter().skip(1) {
+            let dropped = *hop == drone_id;
+            self.drone_reliability
+                .entry(*hop)
+                .or_default()
+                .observe(dropped);
+            if dropped {
+                break;
+            }
+        }
+        debug!(
+            "Server {}: Drone {} drop rate estimated at {:.2}",
+            self.id,
+            drone_id,
+            self.drone_reliability(drone_id).drop_rate()
+        );
+
+        self.reevaluate_routes();
+    }
+
+    /// Invalidates the cached routes when routing by ETX and the estimate of a drone moved by at least
+    /// `REROUTE_THRESHOLD` since they were computed, either because of new observations or because it
+    /// decayed toward the default. Runs after every observation and periodically from the listener.
+    pub(crate) fn reevaluate_routes(&mut self) {
+        if self.routing_policy != RoutingPolicy::ExpectedTransmissions
+            || !self
+                .drone_reliability
+                .values()
+                .any(DroneReliability::drifted)
+        {
+            return;
+        }
+
+        debug!(
+            "Server {}: Drone reliability changed, recomputing the routes",
+            self.id
+        );
+        self.invalidate_route_cache();
+        for reliability in self.drone_reliability.values_mut() {
+            reliability.routed_drop_rate = reliability.drop_rate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::packet::NodeType;
+
+    fn reliability(drop_rate: f64) -> DroneReliability {
+        DroneReliability {
+            drop_rate,
+            routed_drop_rate: drop_rate,
+            ..DroneReliability::default()
+        }
+    }
+
+    /// Server 1 reaches client 9 through the lossy drone 2, or through the reliable drones 3 and 4
+    fn etx_server() -> RustBustersServer {
+        let mut server = RustBustersServer::for_tests(1);
+        server.routing_policy = RoutingPolicy::ExpectedTransmissions;
+        for (node, node_type) in [
+            (2, NodeType::Drone),
+            (3, NodeType::Drone),
+            (4, NodeType::Drone),
+            (9, NodeType::Client),
+        ] {
+            server.known_node_types.insert(node, node_type);
+        }
+        for (a, b) in [(1, 2), (2, 9), (1, 3), (3, 4), (4, 9)] {
+            server.topology.entry(a).or_default().push(b);
+            server.topology.entry(b).or_default().push(a);
+        }
+        server
+    }
+
+    #[test]
+    fn prefers_longer_reliable_route() {
+        let mut server = etx_server();
+        assert_eq!(server.find_route(9), Some(vec![1, 2, 9]));
+
+        server.drone_reliability.insert(2, reliability(0.8));
+        assert_eq!(server.find_route(9), Some(vec![1, 3, 4, 9]));
+    }
+
+    #[test]
+    fn decays_toward_default_drop_rate() {
+        let mut estimate = reliability(0.9);
+        estimate.updated_at = Instant::now().checked_sub(DROP_RATE_HALF_LIFE * 2).unwrap();
+        let expected = DEFAULT_DROP_RATE + (0.9 - DEFAULT_DROP_RATE) / 4.0;
+        assert!((estimate.drop_rate() - expected).abs() < 0.01);
+        assert!(estimate.drifted());
+    }
+
+    #[test]
+    fn invalidates_routes_when_an_estimate_crosses_the_threshold() {
+        let mut server = etx_server();
+        server.drone_reliability.insert(2, reliability(0.8));
+        server.route_cache.insert(9, vec![1, 3, 4, 9]);
+
+        // A small change keeps the cached routes
+        server.record_delivery(&[1, 3, 4, 9]);
+        assert!(server.route_cache.contains_key(&9));
+
+        // Deliveries through drone 2 bring its estimate down by more than the threshold
+        server.record_delivery(&[1, 2, 9]);
+        assert!(server.route_cache.is_empty());
+        assert!(!server.drone_reliability[&2].drifted());
+    }
+}
@@ -1,9 +1,86 @@
-use crate::RustBustersServer;
-use log::info;
-use std::collections::{HashMap, HashSet, VecDeque};
+use crate::server::config::RoutingPolicy;
+use crate::{RustBustersServer, StatsManager};
+use log::{debug, info};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use wg_2024::{network::NodeId, packet::NodeType};
 
+/// Entry of the Dijkstra priority queue, ordered so that the cheapest node is popped first.
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    cost: f64,
+    node: NodeId,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl RustBustersServer {
+    /// Returns the route toward the specified destination, using the route cache when possible.
+    ///
+    /// ### Parameters
+    /// - `destination_id`: The ID of the node to which a route is being searched.
+    ///
+    /// ### Behavior
+    /// - On a cache hit, returns the cached route without exploring the topology.
+    /// - On a cache miss, computes the route with `find_route()` and caches it if found.
+    /// - Hits and misses are counted in the server's stats.
+    pub(crate) fn get_route(&mut self, destination_id: NodeId) -> Option<Vec<NodeId>> {
+        if let Some(route) = self.route_cache.get(&destination_id) {
+            StatsManager::inc_route_cache_hits(self.id);
+            return Some(route.clone());
+        }
+
+        StatsManager::inc_route_cache_misses(self.id);
+        let route = self.find_route(destination_id)?;
+        self.route_cache.insert(destination_id, route.clone());
+        Some(route)
+    }
+
+    /// Clears the route cache. Must be called whenever `topology` is mutated.
+    pub(crate) fn invalidate_route_cache(&mut self) {
+        if !self.route_cache.is_empty() {
+            debug!("Server {}: Invalidating route cache", self.id);
+            self.route_cache.clear();
+        }
+    }
+
+    /// Finds a route from the current server to the specified destination according to the `routing_policy`.
+    ///
+    /// ### Parameters
+    /// - `destination_id`: The ID of the node to which a route is being searched.
+    ///
+    /// ### Returns
+    /// - `Some(Vec<NodeId>)`: A vector representing the route from `self.id` to `destination_id`, if found.
+    /// - `None`: If no valid route exists.
+    pub(crate) fn find_route(&self, destination_id: NodeId) -> Option<Vec<NodeId>> {
+        let route = match self.routing_policy {
+            RoutingPolicy::HopCount => self.find_route_by_hop_count(destination_id),
+            RoutingPolicy::ExpectedTransmissions => self.find_route_by_etx(destination_id),
+        };
+        if let Some(path) = &route {
+            info!(
+                "Server {}: Found route to {}: {:?}",
+                self.id, destination_id, path
+            );
+        }
+        route
+    }
+
     /// Finds the shortest path (number of edges) from the current server to the specified destination using Breadth-First Search (BFS).
     ///
     /// ### Parameters
@@ -15,7 +92,7 @@ impl RustBustersServer {
     ///
     /// ### Algorithm
     /// Uses BFS to explore the network graph and determine the shortest path.
-    pub(crate) fn find_route(&self, destination_id: NodeId) -> Option<Vec<NodeId>> {
+    fn find_route_by_hop_count(&self, destination_id: NodeId) -> Option<Vec<NodeId>> {
         // Simple BFS to find the shortest path
         let mut visited = HashSet::new();
         let mut queue = VecDeque::new();
@@ -38,10 +115,6 @@ impl RustBustersServer {
                     }
                 }
                 path.reverse();
-                info!(
-                    "Server {}: Found route to {}: {:?}",
-                    self.id, destination_id, path
-                );
                 return Some(path);
             }
 
@@ -54,8 +127,8 @@ impl RustBustersServer {
                         .clone();
                     if !visited.contains(&neighbor)
                         && (neighbor_type == NodeType::Drone
-                            || (neighbor_type == NodeType::Client && neighbor == destination_id))
-                    // Check if path contains only drone nodes or client is the final destination
+                            || (neighbor_type != NodeType::Drone && neighbor == destination_id))
+                    // Check if path contains only drone nodes or a host (client or server) is the final destination
                     {
                         visited.insert(neighbor);
                         queue.push_back(neighbor);
@@ -67,4 +140,201 @@ impl RustBustersServer {
 
         None // No route found
     }
+
+    /// Finds the route with the lowest expected transmission count (ETX) using Dijkstra's algorithm.
+    ///
+    /// ### Parameters
+    /// - `destination_id`: The ID of the node to which a route is being searched.
+    ///
+    /// ### Returns
+    /// - `Some(Vec<NodeId>)`: The cheapest route from `self.id` to `destination_id`, if found.
+    /// - `None`: If no valid route exists.
+    ///
+    /// ### Algorithm
+    /// Entering a drone costs its ETX, `1 / (1 - drop_rate)`, as estimated by `drone_reliability()`,
+    /// while entering the destination costs a single hop. As in the BFS, only drones can be
+    /// crossed and a host (client or server) can only be the final destination.
+    fn find_route_by_etx(&self, destination_id: NodeId) -> Option<Vec<NodeId>> {
+        let mut costs: HashMap<NodeId, f64> = HashMap::new();
+        let mut predecessors = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        costs.insert(self.id, 0.0);
+        heap.push(Candidate {
+            cost: 0.0,
+            node: self.id,
+        });
+
+        while let Some(Candidate { cost, node }) = heap.pop() {
+            if node == destination_id {
+                // Build the path from self.id to destination_id
+                let mut path = vec![destination_id];
+                let mut current = destination_id;
+                while let Some(&pred) = predecessors.get(&current) {
+                    path.push(pred);
+                    current = pred;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            // Skip outdated queue entries
+            if cost > *costs.get(&node).unwrap_or(&f64::INFINITY) {
+                continue;
+            }
+
+            let Some(neighbors) = self.topology.get(&node) else {
+                continue;
+            };
+            for &neighbor in neighbors {
+                let step_cost = match self.known_node_types.get(&neighbor) {
+                    Some(NodeType::Drone) => self.drone_reliability(neighbor).etx(),
+                    Some(NodeType::Client | NodeType::Server) if neighbor == destination_id => 1.0,
+                    _ => continue,
+                };
+
+                let new_cost = cost + step_cost;
+                if new_cost < *costs.get(&neighbor).unwrap_or(&f64::INFINITY) {
+                    costs.insert(neighbor, new_cost);
+                    predecessors.insert(neighbor, node);
+                    heap.push(Candidate {
+                        cost: new_cost,
+                        node: neighbor,
+                    });
+                }
+            }
+        }
+
+        None // No route found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_utils::HostCommand;
+    use std::time::Instant;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{FloodResponse, Fragment, NackType, Packet, PacketType, FRAGMENT_DSIZE};
+
+    /// Server `id` reaches client 9 through drone 2, or through drones 3 and 4, with the route cached
+    fn cached_server(id: NodeId) -> RustBustersServer {
+        let mut server = RustBustersServer::for_tests(id);
+        for (node, node_type) in [
+            (id, NodeType::Server),
+            (2, NodeType::Drone),
+            (3, NodeType::Drone),
+            (4, NodeType::Drone),
+            (9, NodeType::Client),
+        ] {
+            server.known_node_types.insert(node, node_type);
+        }
+        for (a, b) in [(id, 2), (2, 9), (id, 3), (3, 4), (4, 9)] {
+            server.topology.entry(a).or_default().push(b);
+            server.topology.entry(b).or_default().push(a);
+        }
+        assert_eq!(server.get_route(9), Some(vec![id, 2, 9]));
+        server
+    }
+
+    /// Returns the route cache hits and misses of a server
+    fn cache_counters(server_id: NodeId) -> (u64, u64) {
+        let stats = serde_json::to_value(StatsManager::get_stats(server_id)).unwrap();
+        (
+            stats["routeCacheHits"].as_u64().unwrap(),
+            stats["routeCacheMisses"].as_u64().unwrap(),
+        )
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut server = cached_server(30);
+        assert_eq!(cache_counters(30), (0, 1));
+
+        assert_eq!(server.get_route(9), Some(vec![30, 2, 9]));
+        assert_eq!(cache_counters(30), (1, 1));
+
+        // Unreachable destinations are not cached
+        assert_eq!(server.get_route(7), None);
+        assert_eq!(server.get_route(7), None);
+        assert_eq!(cache_counters(30), (1, 3));
+        assert!(!server.route_cache.contains_key(&7));
+    }
+
+    #[test]
+    fn flood_response_invalidates_routes() {
+        let mut server = cached_server(31);
+
+        // A response with known links only keeps the cached routes
+        server.handle_flood_response(FloodResponse {
+            flood_id: 1,
+            path_trace: vec![(31, NodeType::Server), (2, NodeType::Drone)],
+        });
+        assert!(server.route_cache.contains_key(&9));
+
+        server.handle_flood_response(FloodResponse {
+            flood_id: 1,
+            path_trace: vec![
+                (31, NodeType::Server),
+                (2, NodeType::Drone),
+                (5, NodeType::Drone),
+            ],
+        });
+        assert!(server.route_cache.is_empty());
+    }
+
+    #[test]
+    fn error_in_routing_invalidates_routes() {
+        let mut server = cached_server(32);
+        let packet = Packet {
+            pack_type: PacketType::MsgFragment(Fragment {
+                fragment_index: 0,
+                total_n_fragments: 1,
+                length: 0,
+                data: [0; FRAGMENT_DSIZE],
+            }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![32, 2, 9],
+            },
+            session_id: 1,
+        };
+        server.pending_sent.insert((1, 0), packet);
+
+        server.handle_nack(1, 0, NackType::ErrorInRouting(2), Some(2));
+        assert_eq!(server.route_cache.get(&9), Some(&vec![32, 3, 4, 9]));
+        assert_eq!(
+            server.pending_sent[&(1, 0)].routing_header.hops,
+            vec![32, 3, 4, 9]
+        );
+    }
+
+    #[test]
+    fn sender_changes_invalidate_routes() {
+        let mut server = cached_server(33);
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        server.handle_command(HostCommand::AddSender(5, sender));
+        assert!(server.route_cache.is_empty());
+
+        assert!(server.get_route(9).is_some());
+        server.handle_command(HostCommand::RemoveSender(5));
+        assert!(server.route_cache.is_empty());
+    }
+
+    #[test]
+    fn link_expiry_invalidates_routes() {
+        let mut server = cached_server(34);
+        let expiry_rounds = server.topology_config.edge_expiry_rounds;
+        server.flood_id_counter = 10 + expiry_rounds;
+        for (a, b) in server.topology_links() {
+            server.confirm_link(a, b, server.flood_id_counter);
+        }
+        server
+            .link_confirmations
+            .insert((2, 9), (10, Instant::now()));
+
+        server.close_discovery_round();
+        assert!(server.route_cache.is_empty());
+        assert_eq!(server.get_route(9), Some(vec![34, 3, 4, 9]));
+    }
 }
@@ -0,0 +1,330 @@
+use crate::utils::message::{ClientRoute, ServerTopology, TopologyLink, TopologyNode};
+use crate::{RustBustersServer, StatsManager};
+use common_utils::HostEvent;
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wg_2024::network::NodeId;
+use wg_2024::packet::NodeType;
+
+/// Time a received FloodRequest is remembered, to answer its copies only once.
+const SEEN_FLOOD_REQUEST_RETENTION: Duration = Duration::from_secs(60);
+
+/// Returns the key of the undirected link between two nodes.
+pub(crate) fn link(a: NodeId, b: NodeId) -> (NodeId, NodeId) {
+    (a.min(b), a.max(b))
+}
+
+impl RustBustersServer {
+    /// Returns whether a FloodRequest was already received from the same initiator, with the same
+    /// flood id and through the same last hop, remembering it otherwise.
+    pub(crate) fn is_duplicate_flood_request(
+        &mut self,
+        initiator_id: NodeId,
+        flood_id: u64,
+        last_hop: NodeId,
+    ) -> bool {
+        self.seen_flood_requests
+            .retain(|_, seen_at| seen_at.elapsed() < SEEN_FLOOD_REQUEST_RETENTION);
+        self.seen_flood_requests
+            .insert((initiator_id, flood_id, last_hop), Instant::now())
+            .is_some()
+    }
+
+    /// Records that a link was confirmed by a FloodResponse of the discovery round `round`.
+    pub(crate) fn confirm_link(&mut self, a: NodeId, b: NodeId, round: u64) {
+        let confirmation = self
+            .link_confirmations
+            .entry(link(a, b))
+            .or_insert((round, Instant::now()));
+        if round >= confirmation.0 {
+            *confirmation = (round, Instant::now());
+        }
+    }
+
+    /// Returns every link of `topology`.
+    pub(crate) fn topology_links(&self) -> BTreeSet<(NodeId, NodeId)> {
+        self.topology
+            .iter()
+            .flat_map(|(&from_id, neighbors)| {
+                neighbors.iter().map(move |&to_id| link(from_id, to_id))
+            })
+            .collect()
+    }
+
+    /// Closes the current discovery round, before a new one is launched.
+    ///
+    /// ### Behavior
+    /// 1. Removes from `topology` the links not confirmed for `edge_expiry_rounds` rounds, and forgets the nodes
+    ///    left without links.
+    /// 2. Compares the links with the ones at the end of the previous round and reports the added and removed
+    ///    links to the simulation controller.
+    ///
+    /// ### Returns
+    /// Whether the links changed during the round.
+    pub(crate) fn close_discovery_round(&mut self) -> bool {
+        let round = self.flood_id_counter;
+        let expiry_rounds = self.topology_config.edge_expiry_rounds.max(1);
+        let expired: Vec<(NodeId, NodeId)> = self
+            .link_confirmations
+            .iter()
+            .filter(|(_, (confirmed_round, _))| {
+                round.saturating_sub(*confirmed_round) >= expiry_rounds
+            })
+            .map(|(link, _)| *link)
+            .collect();
+
+        for (a, b) in &expired {
+            debug!(
+                "Server {}: Link {} - {} not confirmed for {} rounds",
+                self.id, a, b, expiry_rounds
+            );
+            self.link_confirmations.remove(&(*a, *b));
+            self.remove_link(*a, *b);
+        }
+        if !expired.is_empty() {
+            StatsManager::add_links_expired(self.id, expired.len() as u64);
+            self.forget_isolated_nodes();
+            self.invalidate_route_cache();
+        }
+
+        let links = self.topology_links();
+        let topology_changed = links != self.topology_snapshot;
+        let added: Vec<(NodeId, NodeId)> =
+            links.difference(&self.topology_snapshot).copied().collect();
+        let removed: Vec<(NodeId, NodeId)> =
+            self.topology_snapshot.difference(&links).copied().collect();
+        if !added.is_empty() || !removed.is_empty() {
+            info!(
+                "Server {}: Topology of round {} changed, added links {:?}, removed links {:?}",
+                self.id, round, added, removed
+            );
+            self.send_to_sc(HostEvent::TopologyChanged(added, removed));
+        }
+        self.topology_snapshot = links;
+        topology_changed
+    }
+
+    /// Returns the topology learned by the server, with the current route to every client.
+    ///
+    /// The routes are computed with `find_route()`, so that inspecting the topology does not fill
+    /// the route cache nor count in its stats.
+    pub(crate) fn server_topology(&self) -> ServerTopology {
+        let mut nodes: Vec<TopologyNode> = self
+            .known_node_types
+            .iter()
+            .map(|(&id, node_type)| TopologyNode {
+                id,
+                node_type: format!("{:?}", node_type),
+            })
+            .collect();
+        nodes.sort_unstable_by_key(|node| node.id);
+
+        let links = self
+            .topology_links()
+            .into_iter()
+            .map(|(from, to)| {
+                let confirmation = self.link_confirmations.get(&(from, to));
+                TopologyLink {
+                    from,
+                    to,
+                    last_confirmed_round: confirmation.map(|(round, _)| *round),
+                    last_confirmed_at: confirmation.and_then(|(_, confirmed_at)| {
+                        let confirmed_at = SystemTime::now().checked_sub(confirmed_at.elapsed())?;
+                        confirmed_at
+                            .duration_since(UNIX_EPOCH)
+                            .ok()
+                            .map(|d| d.as_secs())
+                    }),
+                }
+            })
+            .collect();
+
+        let routes = nodes
+            .iter()
+            .filter(|node| self.known_node_types.get(&node.id) == Some(&NodeType::Client))
+            .map(|node| ClientRoute {
+                client_id: node.id,
+                route: self.find_route(node.id),
+            })
+            .collect();
+
+        ServerTopology {
+            server_id: self.id,
+            nodes,
+            links,
+            routes,
+        }
+    }
+
+    /// Removes a node from `topology`, `known_node_types` and the link confirmations.
+    pub(crate) fn remove_node(&mut self, node_id: NodeId) {
+        self.topology.remove(&node_id);
+        self.topology
+            .values_mut()
+            .for_each(|neighbors| neighbors.retain(|&id| id != node_id));
+        self.known_node_types.remove(&node_id);
+        self.link_confirmations
+            .retain(|(a, b), _| *a != node_id && *b != node_id);
+    }
+
+    fn remove_link(&mut self, a: NodeId, b: NodeId) {
+        if let Some(neighbors) = self.topology.get_mut(&a) {
+            neighbors.retain(|&id| id != b);
+        }
+        if let Some(neighbors) = self.topology.get_mut(&b) {
+            neighbors.retain(|&id| id != a);
+        }
+    }
+
+    /// Forgets the nodes, other than this server, that have no link left.
+    fn forget_isolated_nodes(&mut self) {
+        let isolated: Vec<NodeId> = self
+            .known_node_types
+            .keys()
+            .copied()
+            .filter(|&id| {
+                id != self.id
+                    && self
+                        .topology
+                        .get(&id)
+                        .map_or(true, |neighbors| neighbors.is_empty())
+            })
+            .collect();
+        for node_id in isolated {
+            self.remove_node(node_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the links added and removed reported to the simulation controller, if any
+    fn topology_changes(
+        sc_recv: &crossbeam_channel::Receiver<HostEvent>,
+    ) -> Option<(Vec<(NodeId, NodeId)>, Vec<(NodeId, NodeId)>)> {
+        sc_recv.try_iter().find_map(|event| match event {
+            HostEvent::TopologyChanged(added, removed) => Some((added, removed)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn expires_links_not_confirmed_for_the_expiry_rounds() {
+        let mut server = RustBustersServer::for_tests(1);
+        let (sc_send, sc_recv) = crossbeam_channel::unbounded();
+        server.controller_send = sc_send;
+        for (node, node_type) in [
+            (1, NodeType::Server),
+            (2, NodeType::Drone),
+            (3, NodeType::Client),
+            (4, NodeType::Client),
+        ] {
+            server.known_node_types.insert(node, node_type);
+        }
+        for (a, b) in [(1, 2), (2, 3), (2, 4)] {
+            server.topology.entry(a).or_default().push(b);
+            server.topology.entry(b).or_default().push(a);
+        }
+
+        // The first round reports every link as added
+        server.flood_id_counter = 1;
+        for (a, b) in [(1, 2), (2, 3), (2, 4)] {
+            server.confirm_link(a, b, 1);
+        }
+        assert!(server.close_discovery_round());
+        assert_eq!(
+            topology_changes(&sc_recv),
+            Some((vec![(1, 2), (2, 3), (2, 4)], vec![]))
+        );
+
+        // A link not confirmed is kept until `edge_expiry_rounds` rounds went by
+        let expiry_rounds = server.topology_config.edge_expiry_rounds;
+        server.flood_id_counter = expiry_rounds;
+        server.confirm_link(1, 2, expiry_rounds);
+        server.confirm_link(2, 3, expiry_rounds);
+        assert!(!server.close_discovery_round());
+        assert_eq!(topology_changes(&sc_recv), None);
+
+        server.flood_id_counter = 1 + expiry_rounds;
+        server.confirm_link(1, 2, 1 + expiry_rounds);
+        server.confirm_link(2, 3, 1 + expiry_rounds);
+        assert!(server.close_discovery_round());
+        assert_eq!(topology_changes(&sc_recv), Some((vec![], vec![(2, 4)])));
+        assert!(!server.link_confirmations.contains_key(&(2, 4)));
+        assert!(!server.known_node_types.contains_key(&4));
+        assert_eq!(server.topology_links(), BTreeSet::from([(1, 2), (2, 3)]));
+    }
+
+    #[test]
+    fn answers_each_flood_request_once_per_last_hop() {
+        let mut server = RustBustersServer::for_tests(1);
+        assert!(!server.is_duplicate_flood_request(5, 1, 2));
+        assert!(server.is_duplicate_flood_request(5, 1, 2));
+        assert!(!server.is_duplicate_flood_request(5, 1, 3));
+        assert!(!server.is_duplicate_flood_request(5, 2, 2));
+        assert!(!server.is_duplicate_flood_request(6, 1, 2));
+
+        // Flood requests received long ago are forgotten
+        server.seen_flood_requests.insert(
+            (5, 1, 2),
+            Instant::now() - SEEN_FLOOD_REQUEST_RETENTION - Duration::from_secs(1),
+        );
+        assert!(!server.is_duplicate_flood_request(5, 1, 2));
+    }
+
+    #[test]
+    fn keeps_the_latest_round_confirming_a_link() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.confirm_link(3, 2, 5);
+        assert_eq!(server.link_confirmations[&(2, 3)].0, 5);
+
+        // A late FloodResponse of an older round does not move the confirmation back
+        server.confirm_link(2, 3, 4);
+        assert_eq!(server.link_confirmations[&(2, 3)].0, 5);
+
+        server.confirm_link(2, 3, 6);
+        assert_eq!(server.link_confirmations[&(2, 3)].0, 6);
+        assert_eq!(server.link_confirmations.len(), 1);
+    }
+
+    #[test]
+    fn draws_the_topology_without_touching_the_route_cache() {
+        let mut server = RustBustersServer::for_tests(40);
+        for (node, node_type) in [
+            (40, NodeType::Server),
+            (2, NodeType::Drone),
+            (3, NodeType::Drone),
+            (9, NodeType::Client),
+        ] {
+            server.known_node_types.insert(node, node_type);
+        }
+        for (a, b) in [(40, 2), (2, 9), (40, 3)] {
+            server.topology.entry(a).or_default().push(b);
+            server.topology.entry(b).or_default().push(a);
+        }
+
+        let topology = server.server_topology();
+        assert!(server.route_cache.is_empty());
+        let stats = serde_json::to_value(StatsManager::get_stats(40)).unwrap();
+        assert_eq!(stats["routeCacheHits"], 0);
+        assert_eq!(stats["routeCacheMisses"], 0);
+
+        let expected = [
+            "graph server_40 {",
+            "    2 [label=\"Drone 2\", shape=ellipse];",
+            "    3 [label=\"Drone 3\", shape=ellipse];",
+            "    9 [label=\"Client 9\", shape=diamond];",
+            "    40 [label=\"Server 40\", shape=box, style=bold];",
+            "    2 -- 9 [color=blue];",
+            "    2 -- 40 [color=blue];",
+            "    3 -- 40;",
+            "}",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(topology.to_dot(), expected);
+    }
+}
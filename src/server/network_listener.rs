@@ -10,11 +10,12 @@ use crossbeam::select;
 use crossbeam_channel::{select_biased, unbounded, Receiver, RecvTimeoutError, Sender};
 use log::{debug, error, info, warn};
 use rand::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::hash::Hash;
 use std::sync::{Arc, LazyLock, Mutex};
 use std::thread::{self, sleep, JoinHandle};
 use std::time::{Duration, Instant};
+use uuid::Uuid;
 use wg_2024::config::Server;
 use wg_2024::network::NodeId;
 use wg_2024::network::SourceRoutingHeader;
@@ -25,7 +26,19 @@ use common_utils::{HostMessage, ServerToClientMessage, User};
 
 use std::collections::HashSet;
 
-use super::db::DbMessage;
+use super::ad::assembler::PendingSession;
+use super::ad::codec::Encoding;
+use super::ad::fec::FecSession;
+use super::builder::RustBustersServerBuilder;
+use super::config::{
+    FecConfig, MailboxConfig, OutboundQueueConfig, PresenceConfig, ReassemblyConfig,
+    ReplicationConfig, RetransmissionConfig, RoutingPolicy, TopologyConfig,
+};
+use super::db::{DbMessage, SearchFilter, SearchResult};
+use super::network::discovery::DiscoveryScheduler;
+use super::network::reliability::DroneReliability;
+use super::peer::relay::PendingRelay;
+use super::peer::NetworkPayload;
 
 pub struct RustBustersServer {
     // Basic configuration
@@ -40,19 +53,70 @@ pub struct RustBustersServer {
     pub(crate) known_node_types: HashMap<NodeId, NodeType>, // node_id -> node_type (Drone/Client/Server)
     pub(crate) topology: HashMap<NodeId, Vec<NodeId>>,
 
+    // Route selection
+    pub(crate) routing_policy: RoutingPolicy,
+    pub(crate) drone_reliability: HashMap<NodeId, DroneReliability>, // drone_id -> observed reliability
+    pub(crate) route_cache: HashMap<NodeId, Vec<NodeId>>,            // destination_id -> route
+
     pub(crate) flood_id_counter: u64,
     pub(crate) session_id_counter: u64,
 
     pub(crate) pending_sent: HashMap<(u64, u64), Packet>, // (session_id, fragment_index) -> packet
-    pub(crate) pending_received: HashMap<u64, (Vec<Option<Fragment>>, u64)>, // session_id -> (fragments, num_fragments) (u8 is the number of fragments received) (for reassembly)
-    pub(crate) sessions_info: HashMap<u64, (NodeId, Instant, HostMessage)>, // session_id -> (destination, instant, message)
+    pub(crate) pending_received: HashMap<(NodeId, u64), PendingSession>, // (source, session_id) -> fragments received so far (for reassembly)
+    pub(crate) reassembly: ReassemblyConfig,
+    pub(crate) buffered_bytes: usize, // bytes allocated by the sessions in pending_received
+    pub(crate) completed_sessions: HashMap<(NodeId, u64), Instant>, // (source, session_id) -> instant of completion
+    pub(crate) sessions_info: HashMap<u64, (NodeId, Instant, NetworkPayload)>, // session_id -> (destination, instant, message)
+    pub(crate) session_messages: HashMap<u64, Uuid>, // session_id -> id of the stored private message it carries
+    pub(crate) mailbox_deliveries: HashMap<Uuid, String>, // id of the stored private message -> id of the mailbox message it delivers
+
+    // Encoding and compression of the sessions toward the other servers
+    pub(crate) peer_encoding: Encoding,
+    pub(crate) binary_peers: HashSet<NodeId>, // servers that advertised binary decoding
+    pub(crate) compression: bool,
+
+    // Forward error correction of the sessions toward the other servers
+    pub(crate) fec: FecConfig,
+    pub(crate) fec_sessions: HashMap<u64, FecSession>, // session_id -> data fragments and acks of the session
+
+    // Retransmission of unacknowledged fragments
+    pub(crate) retransmission: RetransmissionConfig,
+    pub(crate) retransmission_timers: HashMap<(u64, u64), (Instant, u32)>, // (session_id, fragment_index) -> (deadline, retries)
+
+    // Messages toward destinations without a known route
+    pub(crate) outbound_queue: OutboundQueueConfig,
+    pub(crate) unroutable: HashMap<NodeId, VecDeque<(Instant, NetworkPayload, Option<Uuid>)>>, // destination_id -> (parked at, message, stored message id)
 
     // Map for storing the active user sessions
     pub(crate) active_users: HashMap<NodeId, String>,
 
+    // Expiry of the users that stopped talking to the server
+    pub(crate) presence: PresenceConfig,
+    pub(crate) last_activity: HashMap<NodeId, Instant>, // user_id -> last message received
+    pub(crate) delivery_failures: HashMap<NodeId, u32>, // user_id -> consecutive failed sessions
+
+    // Users registered on the other servers and messages relayed to them
+    pub(crate) directory_peers: HashSet<NodeId>, // servers the user directory was exchanged with
+    pub(crate) remote_users: HashMap<NodeId, (NodeId, String)>, // user_id -> (server_id, name)
+    pub(crate) pending_relays: HashMap<String, PendingRelay>, // relay_id -> relayed message waiting for its RelayAck
+    pub(crate) seen_relays: HashMap<String, Instant>, // relay_id -> first time the relayed message was seen
+    pub(crate) relay_deliveries: HashMap<Uuid, (NodeId, String)>, // id of the relayed message delivered locally -> (origin server, relay_id)
+
+    // Replication of the stored messages to the other servers
+    pub(crate) replication: ReplicationConfig,
+    pub(crate) replication_peers: HashSet<NodeId>, // servers the stored messages were sent to
+    pub(crate) replication_backlog: HashMap<NodeId, i64>, // server_id -> timestamp of the oldest message it missed
+
+    // Messages for users that are not registered
+    pub(crate) mailbox: MailboxConfig,
+    pub(crate) last_mailbox_purge: Instant,
+
     // Network discovery
-    last_discovery: Instant,      // last time the network discovery was made
-    discovery_interval: Duration, // the interval at which to perform the discovery
+    pub(crate) discovery_scheduler: DiscoveryScheduler, // when to perform the next discovery
+    pub(crate) topology_config: TopologyConfig,
+    pub(crate) link_confirmations: HashMap<(NodeId, NodeId), (u64, Instant)>, // link -> (last discovery round and instant it was confirmed)
+    pub(crate) topology_snapshot: BTreeSet<(NodeId, NodeId)>, // links at the end of the previous discovery round
+    pub(crate) seen_flood_requests: HashMap<(NodeId, u64, NodeId), Instant>, // (initiator, flood_id, last hop) -> instant received
 
     // Database manager
     pub(crate) db_manager: Result<DbManager, rusqlite::Error>, // manages the internal server's database
@@ -92,8 +156,41 @@ impl RustBustersServer {
         server_controller_sender: Sender<HostCommand>,
         discovery_interval: Option<Duration>,
     ) -> Self {
-        let discovery_interval = discovery_interval.unwrap_or(Duration::from_secs(30));
-        let db_name = format!("server_{}.db", id);
+        let mut builder = Self::builder(
+            id,
+            controller_send,
+            controller_recv,
+            packet_send,
+            packet_recv,
+            server_controller_sender,
+        );
+        if let Some(discovery_interval) = discovery_interval {
+            builder = builder.discovery_interval(discovery_interval);
+        }
+        builder.build()
+    }
+
+    /// Returns a builder for a server with the given id and channels, and default settings.
+    pub fn builder(
+        id: NodeId,
+        controller_send: Sender<HostEvent>,
+        controller_recv: Receiver<HostCommand>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+        packet_recv: Receiver<Packet>,
+        server_controller_sender: Sender<HostCommand>,
+    ) -> RustBustersServerBuilder {
+        RustBustersServerBuilder::new(
+            id,
+            controller_send,
+            controller_recv,
+            packet_send,
+            packet_recv,
+            server_controller_sender,
+        )
+    }
+
+    pub(crate) fn from_builder(builder: RustBustersServerBuilder) -> Self {
+        let id = builder.id;
 
         // Init stats for server
         StatsManager::get_or_create_stats(id);
@@ -106,39 +203,98 @@ impl RustBustersServer {
         let random_number = rng.gen_range(1000..=2000); // Generates a number between 1 and 1000
 
         info!("Server {} spawned succesfully", id);
-        Self {
+        let mut server = Self {
             id,
-            controller_send,
-            controller_recv,
-            packet_send,
-            packet_recv,
+            controller_send: builder.controller_send,
+            controller_recv: builder.controller_recv,
+            packet_send: builder.packet_send,
+            packet_recv: builder.packet_recv,
             ws_receiver,
-            server_controller_sender,
+            server_controller_sender: builder.server_controller_sender,
             known_node_types: HashMap::new(),
             topology: HashMap::new(),
+            routing_policy: builder.routing_policy,
+            drone_reliability: HashMap::new(),
+            route_cache: HashMap::new(),
             flood_id_counter: random_number,
             session_id_counter: 0,
             pending_sent: HashMap::new(),
             pending_received: HashMap::new(),
+            reassembly: builder.reassembly,
+            buffered_bytes: 0,
+            completed_sessions: HashMap::new(),
             sessions_info: HashMap::new(),
+            session_messages: HashMap::new(),
+            mailbox_deliveries: HashMap::new(),
+            peer_encoding: builder.peer_encoding,
+            binary_peers: HashSet::new(),
+            compression: builder.compression,
+            fec: builder.fec,
+            fec_sessions: HashMap::new(),
+            retransmission: builder.retransmission,
+            retransmission_timers: HashMap::new(),
+            outbound_queue: builder.outbound_queue,
+            unroutable: HashMap::new(),
             active_users: HashMap::new(),
-            last_discovery: Instant::now(),
-            discovery_interval,
-            db_manager: DbManager::new(id, db_name),
+            presence: builder.presence,
+            last_activity: HashMap::new(),
+            delivery_failures: HashMap::new(),
+            directory_peers: HashSet::new(),
+            remote_users: HashMap::new(),
+            pending_relays: HashMap::new(),
+            seen_relays: HashMap::new(),
+            relay_deliveries: HashMap::new(),
+            replication: builder.replication,
+            replication_peers: HashSet::new(),
+            replication_backlog: HashMap::new(),
+            mailbox: builder.mailbox,
+            last_mailbox_purge: Instant::now(),
+            discovery_scheduler: DiscoveryScheduler::new(
+                builder.discovery_interval,
+                builder.discovery,
+            ),
+            topology_config: builder.topology,
+            link_confirmations: HashMap::new(),
+            topology_snapshot: BTreeSet::new(),
+            seen_flood_requests: HashMap::new(),
+            db_manager: DbManager::new(id, &builder.db_location),
             has_stopped: false,
-        }
+        };
+
+        StatsManager::set_discovery_interval_ms(
+            id,
+            server.discovery_scheduler.interval().as_millis() as u64,
+        );
+        // Users registered when the server last stopped keep their names until they register again
+        server.restore_users();
+        server
     }
 
     /// Launches the handling of packets, simulation controller commands and UI requests.
     fn launch_network_listener(&mut self) {
         // Listen for incoming messages
         loop {
-            if (self.last_discovery.elapsed() >= self.discovery_interval) {
+            if self.discovery_scheduler.is_due() {
                 info!("Server {} - Discovering network", self.id);
                 self.launch_network_discovery();
-                self.last_discovery = Instant::now();
             }
 
+            // Retransmit the fragments that have not been acked in time
+            self.check_retransmissions();
+            // Drop the parked messages whose destination did not become reachable in time
+            self.expire_unroutable();
+            // Remove the mailbox messages older than the retention period
+            self.purge_mailbox();
+            // Unregister the users that stopped talking to the server
+            self.evict_idle_users();
+            // Fail the relayed messages that were not acknowledged in time
+            self.expire_relays();
+            // Discard the sessions that stopped receiving fragments
+            self.expire_pending_sessions();
+            // Recompute the routes when the reliability estimates decayed since they were cached
+            self.reevaluate_routes();
+            let timeout = self.next_timer_timeout();
+
             select_biased! {
                 // Handle Simulation Controller commands
                 recv(self.controller_recv) -> command => {
@@ -171,8 +327,8 @@ impl RustBustersServer {
                     }
                 }
 
-                // No more packets
-                default(Duration::from_millis(1000)) => {
+                // No more packets: wake up at the latest when the next retransmission is due
+                default(timeout) => {
                     thread::yield_now(); // Give other threads CPU time
                 }
             }
@@ -181,11 +337,30 @@ impl RustBustersServer {
         info!("[SERVER-{}] Terminating RustBustersServer thread", self.id);
     }
 
-    fn handle_ws_request(&self, message: WebSocketRequest) {
+    fn handle_ws_request(&mut self, message: WebSocketRequest) {
         match message {
             WebSocketRequest::GetStats => self.send_stats(),
             WebSocketRequest::GetMessages => self.send_db_messages(),
             WebSocketRequest::GetActiveUsers => self.send_active_users(),
+            WebSocketRequest::GetRooms => self.send_rooms(),
+            WebSocketRequest::Announce {
+                message,
+                recipients,
+                reply,
+            } => {
+                let recipients = self.announce(&message, recipients.as_deref());
+                // Answer directly to the HTTP server
+                let _ = reply.send(recipients);
+            }
+            WebSocketRequest::SearchMessages {
+                query,
+                filter,
+                reply,
+            } => self.search_db_messages(&query, &filter, reply),
+            WebSocketRequest::GetTopology { reply } => {
+                // Answer directly to the HTTP server
+                let _ = reply.send(self.server_topology());
+            }
             _ => {}
         }
     }
@@ -214,6 +389,25 @@ impl RustBustersServer {
         }
     }
 
+    pub(crate) fn search_db_messages(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        reply: Sender<Result<Vec<SearchResult>, String>>,
+    ) {
+        let results = match &self.db_manager {
+            Ok(db_manager) => db_manager
+                .search(query, filter)
+                .map_err(|err| format!("Search failed: {err}")),
+            Err(err) => Err(format!("Database not available: {err}")),
+        };
+        if let Err(err) = &results {
+            warn!("[DB-{}] {}", self.id, err);
+        }
+        // Answer directly to the HTTP server, also on failure so that it does not wait for the timeout
+        let _ = reply.send(results);
+    }
+
     pub(crate) fn send_active_users(&self) {
         let active_users = self.get_active_users();
         InternalChannelsManager::send_active_users(self.id, active_users);
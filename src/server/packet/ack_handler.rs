@@ -1,8 +1,10 @@
 use std::time::Instant;
 
+use crate::server::db::DeliveryState;
+use crate::server::peer::NetworkPayload;
 use crate::{RustBustersServer, StatsManager};
 use common_utils::{HostEvent, HostMessage};
-use log::{info, warn};
+use log::{debug, info, warn};
 
 impl RustBustersServer {
     /// Handles an Acknowledgment (ACK) packet for a sent message fragment.
@@ -12,28 +14,55 @@ impl RustBustersServer {
     /// - `fragment_index: u64` – The index of the acknowledged fragment within the session.
     ///
     /// ### Behavior
-    /// 1. Removes the acknowledged fragment `(session_id, fragment_index)` from `pending_sent`.
-    /// 2. Checks if all fragments for `session_id` have been acknowledged.
+    /// 1. Removes the acknowledged fragment `(session_id, fragment_index)` from `pending_sent`
+    ///    together with its retransmission timer, and credits the drones of its route in the reliability estimates.
+    ///    Acks of fragments that are not pending are ignored.
+    /// 2. Checks if all fragments for `session_id` have been acknowledged. A session with forward error correction
+    ///    only needs as many acks as its data fragments: its other fragments are then given up.
     /// 3. If all fragments are acknowledged:
-    ///    - Removes session information from `sessions_info` map.
+    ///    - Removes session information from `sessions_info` map and marks the stored message it carries as acked.
+    ///    - Resets the delivery failures of the destination.
     ///    - Computes the delay since the session started.
-    ///    - Sends a `HostMessageSent` event to the simulation controller.
+    ///    - Sends a `HostMessageSent` event to the simulation controller, unless the session carried a message for another server.
     pub(crate) fn handle_ack(&mut self, session_id: u64, fragment_index: u64) {
-        // Remove the acked fragment from the pending_sent list
-        self.pending_sent.remove(&(session_id, fragment_index));
+        // Remove the acked fragment from the pending_sent list and stop its retransmission timer
+        let Some(packet) = self.pending_sent.remove(&(session_id, fragment_index)) else {
+            debug!(
+                "Server {}: Ack for fragment {} of session {} not pending",
+                self.id, fragment_index, session_id
+            );
+            return;
+        };
+        // Every drone on the route forwarded the fragment
+        self.record_delivery(&packet.routing_header.hops);
+        self.retransmission_timers
+            .remove(&(session_id, fragment_index));
 
         // Check if all fragments with key (session_id, _) have been acked
-        if self
-            .pending_sent
-            .iter()
-            .filter(|((key, _), _)| *key == session_id)
-            .collect::<Vec<_>>()
-            .is_empty()
-        {
+        let session_acked = match self.fec_sessions.get_mut(&session_id) {
+            Some(fec_session) => {
+                fec_session.acked += 1;
+                fec_session.is_complete()
+            }
+            None => !self.pending_sent.keys().any(|(key, _)| *key == session_id),
+        };
+        if session_acked {
+            if self.fec_sessions.remove(&session_id).is_some() {
+                self.pending_sent.retain(|(id, _), _| *id != session_id);
+                self.retransmission_timers
+                    .retain(|(id, _), _| *id != session_id);
+            }
+
             // Sending host message sent to simulation controller
-            if let Some((dest_id, start, host_message)) = self.sessions_info.remove(&session_id) {
-                let delay = Instant::now() - start;
-                self.send_to_sc(HostEvent::HostMessageSent(dest_id, host_message, delay));
+            if let Some((dest_id, start, payload)) = self.sessions_info.remove(&session_id) {
+                self.complete_session_delivery(session_id, DeliveryState::Acked);
+                self.record_session_outcome(dest_id, true);
+
+                // Messages for other servers are not reported to the simulation controller
+                if let NetworkPayload::Host(host_message) = payload {
+                    let delay = Instant::now() - start;
+                    self.send_to_sc(HostEvent::HostMessageSent(dest_id, host_message, delay));
+                }
 
                 info!(
                     "Server {}: All fragments of session {} acked",
@@ -0,0 +1,56 @@
+use crate::{RustBustersServer, StatsManager};
+use chrono::Utc;
+use common_utils::{HostMessage, MessageBody, MessageContent, ServerToClientMessage};
+use log::info;
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Sends an operator announcement to the active users.
+    ///
+    /// ### Parameters
+    /// - `text: &str` – The content of the announcement.
+    /// - `recipients: Option<&[NodeId]>` – The users to be reached, `None` for every active user.
+    ///   Recipients that are not registered are skipped.
+    ///
+    /// ### Behavior
+    /// The announcement is sent to each recipient as a `PrivateMessage` from the server, saved with the
+    /// other private messages and tracked like them until it is acked or fails.
+    ///
+    /// ### Returns
+    /// The users the announcement was sent to.
+    pub(crate) fn announce(&mut self, text: &str, recipients: Option<&[NodeId]>) -> Vec<NodeId> {
+        let mut targets: Vec<NodeId> = self
+            .active_users
+            .keys()
+            .filter(|id| recipients.map_or(true, |recipients| recipients.contains(id)))
+            .copied()
+            .collect();
+        targets.sort_unstable();
+
+        let message = MessageBody {
+            sender_id: self.id,
+            content: MessageContent::Text(text.to_string()),
+            timestamp: Utc::now().timestamp().to_string(),
+        };
+
+        for &dest_id in &targets {
+            let db_message_id = self.store_message(self.id, dest_id, &message);
+            self.send_tracked_message(
+                dest_id,
+                HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
+                    sender_id: self.id,
+                    message: message.clone(),
+                }),
+                db_message_id,
+            );
+        }
+
+        StatsManager::inc_announcements_sent(self.id);
+        info!(
+            "Server {}: Announcement sent to {} users",
+            self.id,
+            targets.len()
+        );
+        targets
+    }
+}
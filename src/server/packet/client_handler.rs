@@ -2,23 +2,30 @@ use crate::{InternalChannelsManager, RustBustersServer};
 use common_utils::{
     ClientToServerMessage, HostMessage, MessageBody, MessageContent, ServerToClientMessage, User,
 };
+use log::warn;
+use std::time::Instant;
 use uuid::Uuid;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
-use wg_2024::packet::{Ack, Fragment, Packet, PacketType};
+use wg_2024::packet::{Ack, Fragment, NodeType, Packet, PacketType};
 
-use crate::server::db::DbMessage;
+use crate::server::db::{DbMessage, MessageKind};
+
+/// Maximum number of messages sent in a single page of history
+const MAX_HISTORY_PAGE: usize = 50;
 
 impl RustBustersServer {
     /// Handles user registration
     ///
     /// ### Behavior
-    /// 1. Inserts the `src_id` in the `active_users` map and sends the corresponding message back.
-    /// 2. Notifies the other active users connected to the server.
+    /// 1. Inserts the `src_id` in the `active_users` map, records it in the user registry
+    ///    and sends the corresponding message back. Names reserved by other nodes are refused.
+    /// 2. Delivers the messages stored in the mailbox of `src_id` while it was offline.
+    /// 3. Notifies the other active users connected to the server.
     pub(crate) fn handle_register_user(&mut self, src_id: NodeId, name: &str) {
         // Verify the user presence in the hashset
         if !self.active_users.contains_key(&src_id) {
-            // Name already taken
-            if self.active_users.values().any(|n| n == name) {
+            // Name already taken, now or in a previous run
+            if self.is_name_reserved(src_id, name) {
                 self.send_network_message(
                     src_id,
                     HostMessage::FromServer(ServerToClientMessage::RegistrationFailure {
@@ -30,12 +37,21 @@ impl RustBustersServer {
 
             // Newly inserted
             self.active_users.insert(src_id, name.to_string());
+            self.last_activity.insert(src_id, Instant::now());
+            self.record_registration(src_id, name);
             // Send Registration Success message
             self.send_network_message(
                 src_id,
                 HostMessage::FromServer(ServerToClientMessage::RegistrationSuccess),
             );
 
+            // Deliver the messages received while the user was offline
+            self.deliver_offline_messages(src_id);
+
+            // The user is now reachable through this server
+            self.remote_users.remove(&src_id);
+            self.publish_directory();
+
             // Send active users to Internal Channels for retransmission to WebSoket Server and finally to the UI
             self.send_active_users();
 
@@ -68,29 +84,18 @@ impl RustBustersServer {
     ///
     /// ### Behavior
     /// 1. Removes the `src_id` from the `active_users` map and sends the corresponding message back.
+    ///    Its name stays reserved in the user registry.
     /// 2. Notifies the other active users connected to the server.
     pub(crate) fn handle_unregister_user(&mut self, src_id: NodeId) {
         // Verify the user presence in the hashset
         if self.active_users.contains_key(&src_id) {
-            self.active_users.remove(&src_id);
-
             // Send Unregistration Success message
             self.send_network_message(
                 src_id,
                 HostMessage::FromServer(ServerToClientMessage::UnregisterSuccess),
             );
 
-            // Send active users to Internal Channels for retransmission to WebSoket client and finally to the UI
-            self.send_active_users();
-
-            // Cloning because of borrow checker issues
-            let other_users = self.active_users.clone();
-            other_users.iter().for_each(|(&other_id, other_name)| {
-                self.send_network_message(
-                    other_id,
-                    HostMessage::FromServer(ServerToClientMessage::UserUnregistered { id: src_id }),
-                );
-            });
+            self.remove_active_user(src_id);
         } else {
             // Already unregistered
             // Send Unregsiteration Failure message
@@ -106,14 +111,14 @@ impl RustBustersServer {
     /// Handles request for the list of active users
     ///
     /// ### Behavior
-    /// 1. Sends the list of active users to the requester.
+    /// 1. Sends the list of active users to the requester, including the users registered on the other servers.
     /// 2. Sends the list of active users through the Internal Channels to the UI
     pub(crate) fn handle_request_active_users(&mut self, src_id: NodeId) {
         // Send the list of active users
         self.send_network_message(
             src_id,
             HostMessage::FromServer(ServerToClientMessage::ActiveUsersList {
-                users: self.get_active_users(),
+                users: self.get_reachable_users(),
             }),
         );
 
@@ -125,8 +130,11 @@ impl RustBustersServer {
     ///
     /// ### Behavior
     /// 1. Verifies if the `src_id` and `dest_id` are registered.
-    /// 2. Sends the message to the recipient.
-    /// 3. Save the received message to the local database via the `db_manager`.
+    ///    If `dest_id` is registered on another server, the message is relayed to that server.
+    ///    If `dest_id` is a known client or a user of the registry that is not registered,
+    ///    the message is stored in its mailbox.
+    /// 2. Save the received message to the local database via the `db_manager`.
+    /// 3. Sends the message to the recipient, tracking its delivery state.
     pub(crate) fn handle_send_private_message(
         &mut self,
         src_id: NodeId,
@@ -144,29 +152,125 @@ impl RustBustersServer {
 
         // Check if the recipient is an active user
         if !self.active_users.contains_key(&dest_id) {
-            self.send_network_message(
-                src_id,
-                HostMessage::FromServer(ServerToClientMessage::UserNotFound { user_id: dest_id }),
-            );
+            if let Some(server_id) = self.remote_user_server(dest_id) {
+                // The recipient is registered on another server
+                let db_message_id = self.store_message(src_id, dest_id, message);
+                self.relay_private_message(src_id, dest_id, server_id, message, db_message_id);
+                return;
+            }
+            if self.known_node_types.get(&dest_id) == Some(&NodeType::Client)
+                || self.is_known_user(dest_id)
+            {
+                // The recipient is a client of the network, or a user of the registry, that is currently offline
+                self.store_offline_message(src_id, dest_id, message);
+            } else {
+                self.send_network_message(
+                    src_id,
+                    HostMessage::FromServer(ServerToClientMessage::UserNotFound {
+                        user_id: dest_id,
+                    }),
+                );
+            }
             return;
         }
-        // Send the message to the recipient
-        self.send_network_message(
+        // Save message to local database, then send it to the recipient tracking its delivery
+        let db_message_id = self.store_message(src_id, dest_id, message);
+        self.send_tracked_message(
             dest_id,
             HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
                 sender_id: src_id,
                 message: message.clone(),
             }),
+            db_message_id,
         );
-        // Save message to local database
-        if let Ok(db_manager) = &self.db_manager {
-            let message_str = match message.content.clone() {
-                MessageContent::Text(text) => text,
-                MessageContent::Image(image) => image,
-                _ => "".to_string(),
-            };
-            if let Ok(new_db_message) = db_manager.insert(src_id, dest_id, message_str) {
-                self.send_db_message(new_db_message);
+    }
+
+    /// Saves a private message to the local database via the `db_manager`, pushes it to the UI
+    /// and replicates it to the other servers.
+    ///
+    /// ### Returns
+    /// - `Some(Uuid)`: The id of the stored message, whose delivery state starts as `Queued`.
+    /// - `None`: If the message could not be stored.
+    pub(crate) fn store_message(
+        &mut self,
+        src_id: NodeId,
+        dest_id: NodeId,
+        message: &MessageBody,
+    ) -> Option<Uuid> {
+        let db_manager = self.db_manager.as_ref().ok()?;
+        let (message_str, kind) = match message.content.clone() {
+            MessageContent::Text(text) => (text, MessageKind::Text),
+            MessageContent::Image(image) => (image, MessageKind::Image),
+            _ => ("".to_string(), MessageKind::Text),
+        };
+        let new_db_message = db_manager.insert(src_id, dest_id, message_str, kind).ok()?;
+        let db_message_id = Uuid::parse_str(new_db_message.id()).ok();
+        self.replicate_message(&new_db_message);
+        self.send_db_message(new_db_message);
+        db_message_id
+    }
+
+    /// Handles a request for the history of the conversation with another user
+    ///
+    /// ### Parameters
+    /// - `src_id: NodeId` – The requester.
+    /// - `peer_id: NodeId` – The other user of the conversation.
+    /// - `before: Option<String>` – Only messages older than the message with this id are returned,
+    ///   `None` starts from the latest message.
+    /// - `limit: usize` – The maximum number of messages in the page, capped at `MAX_HISTORY_PAGE`.
+    ///
+    /// ### Behavior
+    /// 1. Verifies that `src_id` is registered.
+    /// 2. Retrieves the page from the local database via the `db_manager`.
+    /// 3. Sends the page back to the requester, oldest message first, telling whether older messages exist
+    ///    and, if so, the id of the oldest message of the page to be used as `before` for the next page.
+    pub(crate) fn handle_request_history(
+        &mut self,
+        src_id: NodeId,
+        peer_id: NodeId,
+        before: Option<String>,
+        limit: usize,
+    ) {
+        // Check if the requester is an active user
+        if !self.active_users.contains_key(&src_id) {
+            self.send_network_message(
+                src_id,
+                HostMessage::FromServer(ServerToClientMessage::UserNotFound { user_id: src_id }),
+            );
+            return;
+        }
+
+        let page = match &self.db_manager {
+            Ok(db_manager) => db_manager.get_conversation(
+                src_id,
+                peer_id,
+                before.as_deref(),
+                limit.min(MAX_HISTORY_PAGE),
+            ),
+            Err(_) => Ok((Vec::new(), false)),
+        };
+
+        match page {
+            Ok((db_messages, has_more)) => {
+                let next_before = db_messages
+                    .first()
+                    .filter(|_| has_more)
+                    .map(|db_message| db_message.id().to_string());
+                self.send_network_message(
+                    src_id,
+                    HostMessage::FromServer(ServerToClientMessage::History {
+                        peer_id,
+                        messages: db_messages.iter().map(DbMessage::to_message_body).collect(),
+                        has_more,
+                        next_before,
+                    }),
+                );
+            }
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot retrieve history between {} and {}: {}",
+                    self.id, src_id, peer_id, err
+                );
             }
         }
     }
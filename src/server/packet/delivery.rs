@@ -0,0 +1,48 @@
+use crate::server::db::DeliveryState;
+use crate::RustBustersServer;
+use log::warn;
+use uuid::Uuid;
+
+impl RustBustersServer {
+    /// Updates the delivery state of a stored private message, pushes the updated message to the UI and
+    /// replicates it, so that the other servers update the state of their copy.
+    ///
+    /// ### Parameters
+    /// - `db_message_id: Uuid` – The id of the message in the local database.
+    /// - `delivery_state: DeliveryState` – The new delivery state.
+    ///
+    /// A message delivering a mailbox message completes the mailbox delivery, see `complete_mailbox_delivery()`,
+    /// and a message relayed by another server is acknowledged to it, see `complete_relay_delivery()`.
+    pub(crate) fn set_delivery_state(
+        &mut self,
+        db_message_id: Uuid,
+        delivery_state: DeliveryState,
+    ) {
+        if let Ok(db_manager) = &self.db_manager {
+            match db_manager.update_delivery_state(db_message_id, delivery_state) {
+                Ok(Some(db_message)) => {
+                    self.replicate_message(&db_message);
+                    self.send_db_message(db_message);
+                }
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "Server {}: Cannot update delivery state of message {}: {}",
+                    self.id, db_message_id, err
+                ),
+            }
+        }
+        self.complete_mailbox_delivery(db_message_id, delivery_state);
+        self.complete_relay_delivery(db_message_id, delivery_state);
+    }
+
+    /// Sets the final delivery state of the stored message carried by a session, if any.
+    pub(crate) fn complete_session_delivery(
+        &mut self,
+        session_id: u64,
+        delivery_state: DeliveryState,
+    ) {
+        if let Some(db_message_id) = self.session_messages.remove(&session_id) {
+            self.set_delivery_state(db_message_id, delivery_state);
+        }
+    }
+}
@@ -19,26 +19,47 @@ impl RustBustersServer {
     /// 1. Iterates through `path_trace` in pairs of nodes (windows of size 2).
     /// 2. Extracts `from_id`, `to_id` (node IDs) and their respective types.
     /// 3. Adds both nodes to the `known_node_types` map to track discovered nodes and their types.
-    /// 4. Updates `topology` by ensuring bidirectional connectivity between `from_id` and `to_id`.
+    /// 4. Updates `topology` by ensuring bidirectional connectivity between `from_id` and `to_id`, and records
+    ///    that the link was confirmed in the discovery round of the response (its `flood_id`).
+    /// 5. If a node or a link was learned, invalidates the route cache and flushes the messages
+    ///    parked for the destinations that became reachable.
     pub(crate) fn handle_flood_response(&mut self, flood_response: FloodResponse) {
+        let mut topology_changed = false;
+        let round = flood_response.flood_id;
         for window in flood_response.path_trace.windows(2) {
             if let [(from_id, from_type), (to_id, to_type)] = window {
-                self.known_node_types.insert(*from_id, *from_type);
-                self.known_node_types.insert(*to_id, *to_type);
+                topology_changed |=
+                    self.known_node_types.insert(*from_id, *from_type) != Some(*from_type);
+                topology_changed |=
+                    self.known_node_types.insert(*to_id, *to_type) != Some(*to_type);
 
                 // Update topology
                 let from_to = self.topology.entry(*from_id).or_default();
                 if !from_to.contains(to_id) {
                     from_to.push(*to_id);
+                    topology_changed = true;
                 }
 
                 let to_from = self.topology.entry(*to_id).or_default();
                 if !to_from.contains(from_id) {
                     to_from.push(*from_id);
+                    topology_changed = true;
                 }
+
+                self.confirm_link(*from_id, *to_id, round);
             }
         }
 
+        if topology_changed {
+            self.invalidate_route_cache();
+            // Some parked messages may have become routable
+            self.flush_unroutable();
+            // Exchange the user directory with the servers just found
+            self.exchange_directories();
+            // Bring the other servers up to date with the stored messages
+            self.sync_replicas();
+        }
+
         info!("Server {}: Updated topology: {:?}", self.id, self.topology);
         info!(
             "Server {}: Known nodes: {:?}",
@@ -54,20 +75,39 @@ impl RustBustersServer {
     /// - `session_id: u64` – The session identifier associated with this request.
     ///
     /// ### Behavior
-    /// 1. Clones `path_trace` from the request and appends the server's own ID and type.
-    /// 2. Creates a `FloodResponse` containing the updated path trace.
-    /// 3. If the request was initiated by this server:
+    /// 1. Ignores the copies of a request already received from the same initiator, with the same `flood_id`
+    ///    and through the same last hop.
+    /// 2. Clones `path_trace` from the request and appends the server's own ID and type.
+    /// 3. Creates a `FloodResponse` containing the updated path trace.
+    /// 4. If the request was initiated by this server:
     ///    - Logs the event.
     ///    - Directly calls `handle_flood_response` to update its topology without sending a response.
-    /// 4. Otherwise:
+    /// 5. Otherwise:
     ///    - Constructs a `Packet` with a `FloodResponse`.
     ///    - Sets up source routing by reversing the `path_trace`.
     ///    - Attempts to send the packet to the next hop in the route.
-    /// 5. If sending fails:
+    /// 6. If sending fails:
     ///    - Logs a warning and notifies the simulation controller via `ControllerShortcut`.
-    /// 6. Updates flood response statistics.
-    /// 7. Notifies the simulation controller about the sent `FloodResponse` packet.
+    /// 7. Updates flood response statistics.
+    /// 8. Notifies the simulation controller about the sent `FloodResponse` packet.
     pub(crate) fn handle_flood_request(&mut self, flood_request: FloodRequest, session_id: u64) {
+        let last_hop = flood_request
+            .path_trace
+            .last()
+            .map_or(flood_request.initiator_id, |(id, _)| *id);
+        if self.is_duplicate_flood_request(
+            flood_request.initiator_id,
+            flood_request.flood_id,
+            last_hop,
+        ) {
+            info!(
+                "Server {}: Ignoring duplicate FloodRequest {} of {} through {}",
+                self.id, flood_request.flood_id, flood_request.initiator_id, last_hop
+            );
+            StatsManager::inc_flood_requests_ignored(self.id);
+            return;
+        }
+
         let mut new_path_trace = flood_request.path_trace.clone();
         new_path_trace.push((self.id, Server));
 
@@ -1,3 +1,5 @@
+use crate::server::ad::fec;
+use crate::server::peer::NetworkPayload;
 use crate::RustBustersServer;
 use crate::StatsManager;
 use common_utils::HostEvent;
@@ -6,6 +8,7 @@ use common_utils::{
     ServerToClientMessage, User,
 };
 use log::{info, warn};
+use std::time::Instant;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{Ack, Fragment, Packet, PacketType};
 
@@ -19,13 +22,16 @@ impl RustBustersServer {
     /// - `source_routing_header: SourceRoutingHeader` – The routing information for the fragment.
     ///
     /// ### Behavior
-    /// 1. Stores the received fragment in `pending_received`.
+    /// 1. Stores the received fragment in `pending_received`. Fragments that are inconsistent or exceed the
+    ///    reassembly limits are dropped without an Ack. Duplicate fragments are acked again without being stored.
     /// 2. If all fragments for `session_id` are received:
     ///    - Reassembles the message.
     ///    - If it's a `FromClient` message, handles it based on the message type:
     ///      - Registers/unregisters users.
     ///      - Retrieves the active user list.
     ///      - Sends private messages.
+    ///      - Sends a page of the conversation history.
+    ///    - If it's a message from another server, handles it with `handle_peer_message()`.
     ///    - Logs the successful message reception.
     ///    - Updates message reception statistics.
     /// 3. If reassembly fails, logs an error.
@@ -40,31 +46,79 @@ impl RustBustersServer {
         session_id: u64,
         source_routing_header: SourceRoutingHeader,
     ) {
+        let src_id = source_routing_header
+            .source()
+            .expect("No current hop in source routing header");
+
+        // Fragments that cannot be buffered are dropped without an Ack
+        let session_received = match self.set_pending(src_id, session_id, fragment.clone()) {
+            Ok(session_received) => session_received,
+            Err(err) => {
+                warn!(
+                    "Server {}: Rejecting fragment {} of session {} from {}: {}",
+                    self.id, fragment.fragment_index, session_id, src_id, err
+                );
+                StatsManager::inc_fragments_rejected(self.id);
+                return;
+            }
+        };
+
         // If after insert all fragments of the session are received, reassemble the message
-        if self.set_pending(session_id, fragment.clone()) {
-            match self.reassemble_fragments(session_id) {
+        if session_received {
+            match self.reassemble_fragments(src_id, session_id) {
                 Ok(msg) => {
-                    if let HostMessage::FromClient(client_to_server_msg) = &msg {
-                        let src_id = source_routing_header
-                            .source()
-                            .expect("No current hop in source routing header");
-                        // Handle the various types of Client to Server messages
-                        match client_to_server_msg {
-                            ClientToServerMessage::RegisterUser { name } => {
-                                self.handle_register_user(src_id, name)
+                    match &msg {
+                        NetworkPayload::Host(HostMessage::FromClient(client_to_server_msg)) => {
+                            self.touch_user(src_id);
+                            // Handle the various types of Client to Server messages
+                            match client_to_server_msg {
+                                ClientToServerMessage::RegisterUser { name } => {
+                                    self.handle_register_user(src_id, name)
+                                }
+                                ClientToServerMessage::UnregisterUser => {
+                                    self.handle_unregister_user(src_id)
+                                }
+                                ClientToServerMessage::RequestActiveUsers => {
+                                    self.handle_request_active_users(src_id)
+                                }
+                                ClientToServerMessage::SendPrivateMessage {
+                                    recipient_id,
+                                    message,
+                                } => {
+                                    self.handle_send_private_message(src_id, *recipient_id, message)
+                                }
+                                ClientToServerMessage::CreateRoom { room } => {
+                                    self.handle_create_room(src_id, room)
+                                }
+                                ClientToServerMessage::JoinRoom { room } => {
+                                    self.handle_join_room(src_id, room)
+                                }
+                                ClientToServerMessage::LeaveRoom { room } => {
+                                    self.handle_leave_room(src_id, room)
+                                }
+                                ClientToServerMessage::SendRoomMessage { room, message } => {
+                                    self.handle_send_room_message(src_id, room, message)
+                                }
+                                ClientToServerMessage::RequestRooms => {
+                                    self.handle_request_rooms(src_id)
+                                }
+                                ClientToServerMessage::RequestHistory {
+                                    peer_id,
+                                    before,
+                                    limit,
+                                } => self.handle_request_history(
+                                    src_id,
+                                    *peer_id,
+                                    before.clone(),
+                                    *limit as usize,
+                                ),
+                                _ => {}
                             }
-                            ClientToServerMessage::UnregisterUser => {
-                                self.handle_unregister_user(src_id)
-                            }
-                            ClientToServerMessage::RequestActiveUsers => {
-                                self.handle_request_active_users(src_id)
-                            }
-                            ClientToServerMessage::SendPrivateMessage {
-                                recipient_id,
-                                message,
-                            } => self.handle_send_private_message(src_id, *recipient_id, message),
-                            _ => {}
                         }
+                        NetworkPayload::Server(peer_message) => {
+                            self.handle_peer_message(src_id, peer_message.clone())
+                        }
+                        _ => {}
                     }
                     info!(
                         "Server {}: Received full message {:?} of session {}",
@@ -134,29 +188,171 @@ impl RustBustersServer {
     /// Inserts the received fragment into `pending_received`.
     ///
     /// ### Parameters
+    /// - `src_id: NodeId` – The host that sent the fragment.
     /// - `session_id: u64` – The session identifier associated with the fragment.
     /// - `fragment: Fragment` – The received fragment to be stored.
     ///
     /// ### Returns
-    /// - `true` if all fragments for the session have been received, allowing reassembly.
-    /// - `false` otherwise.
+    /// - `Ok(true)` if all fragments for the session have been received, allowing reassembly.
+    /// - `Ok(false)` otherwise.
+    /// - `Err(String)` if the fragment is not consistent with its session, or the session cannot be buffered
+    ///   (see `open_pending_session()`).
     ///
     /// ### Behavior
-    /// - Stores the fragment at its corresponding index in `pending_received`.
+    /// - Ignores the fragments of the sessions completed recently and the fragments already received,
+    ///   so that a retransmitted fragment or a replayed session is never handled twice.
+    /// - Creates the buffer of the session on its first fragment.
+    /// - Stores the fragment at its corresponding index in `pending_received`, under `(src_id, session_id)`.
     /// - Increments the count of received fragments.
-    /// - If all fragments are received, signals readiness for reassembly.
-    fn set_pending(&mut self, session_id: u64, fragment: Fragment) -> bool {
+    /// - Records the session as completed once all its fragments are received.
+    /// - If all fragments are received, signals readiness for reassembly. A session with forward error
+    ///   correction is ready as soon as it has as many fragments as its data fragments.
+    fn set_pending(
+        &mut self,
+        src_id: NodeId,
+        session_id: u64,
+        fragment: Fragment,
+    ) -> Result<bool, String> {
         let fragment_index = fragment.fragment_index;
         let total_n_fragments = fragment.total_n_fragments;
-        // insert the fragment in the pending_received map at index fragment_index
-        let fragments = vec![None; fragment.total_n_fragments as usize];
-        let entry = self
+        if fragment_index >= total_n_fragments {
+            return Err(format!(
+                "Fragment index {} out of {} fragments",
+                fragment_index, total_n_fragments
+            ));
+        }
+        let required_fragments = if fec::is_fec_fragment(&fragment) {
+            fec::data_fragments(&fragment)
+        } else {
+            total_n_fragments
+        };
+
+        // Fragments of a completed session, or received twice, are acked again but not stored
+        if self.is_completed_session(src_id, session_id) {
+            StatsManager::inc_duplicate_fragments(self.id);
+            return Ok(false);
+        }
+        if !self.pending_received.contains_key(&(src_id, session_id)) {
+            self.open_pending_session(src_id, session_id, total_n_fragments)?;
+        }
+        let session = self
             .pending_received
-            .entry(session_id)
-            .or_insert((fragments, 0));
-        entry.0[fragment_index as usize] = Some(fragment);
-        entry.1 += 1;
+            .get_mut(&(src_id, session_id))
+            .expect("Session buffer just created");
+        if session.fragments.len() as u64 != total_n_fragments {
+            return Err(format!(
+                "Session has {} fragments, fragment announces {}",
+                session.fragments.len(),
+                total_n_fragments
+            ));
+        }
+
+        session.last_update = Instant::now();
+        let slot = &mut session.fragments[fragment_index as usize];
+        if slot.is_some() {
+            StatsManager::inc_duplicate_fragments(self.id);
+            return Ok(false);
+        }
+
+        // insert the fragment in the pending_received map at index fragment_index
+        *slot = Some(fragment);
+        session.received += 1;
 
-        entry.1 == total_n_fragments
+        let session_received = session.received == required_fragments;
+        if session_received {
+            self.record_completed_session(src_id, session_id);
+        }
+        Ok(session_received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ad::codec::Encoding;
+
+    fn register(name: &str) -> NetworkPayload {
+        NetworkPayload::Host(HostMessage::FromClient(
+            ClientToServerMessage::RegisterUser {
+                name: name.repeat(40),
+            },
+        ))
+    }
+
+    #[test]
+    fn keeps_sessions_of_different_sources_apart() {
+        let mut server = RustBustersServer::for_tests(1);
+        let first = server.disassemble_message(&register("first "), Encoding::Json, None);
+        let second = server.disassemble_message(&register("second "), Encoding::Json, None);
+        assert!(first.len() > 1 && second.len() > 1);
+
+        // Both clients picked session 7, their fragments arrive interleaved
+        let mut completed = Vec::new();
+        let mut first_iter = first.into_iter();
+        let mut second_iter = second.into_iter();
+        loop {
+            let mut progressed = false;
+            for (src_id, fragment) in [(2, first_iter.next()), (3, second_iter.next())] {
+                let Some(fragment) = fragment else { continue };
+                progressed = true;
+                if server.set_pending(src_id, 7, fragment).unwrap() {
+                    completed.push((src_id, server.reassemble_fragments(src_id, 7).unwrap()));
+                }
+            }
+            if !progressed {
+                break;
+            }
+        }
+
+        assert_eq!(completed.len(), 2);
+        for (src_id, payload) in completed {
+            let expected = if src_id == 2 {
+                register("first ")
+            } else {
+                register("second ")
+            };
+            assert_eq!(
+                serde_json::to_value(&payload).unwrap(),
+                serde_json::to_value(&expected).unwrap()
+            );
+        }
+        assert!(server.pending_received.is_empty());
+        assert_eq!(server.buffered_bytes, 0);
+    }
+
+    #[test]
+    fn ignores_fragments_received_twice() {
+        let mut server = RustBustersServer::for_tests(1);
+        let fragments = server.disassemble_message(&register("twice "), Encoding::Json, None);
+        let first = fragments[0].clone();
+
+        assert!(!server.set_pending(2, 7, first.clone()).unwrap());
+        assert!(!server.set_pending(2, 7, first).unwrap());
+        assert_eq!(server.pending_received[&(2, 7)].received, 1);
+    }
+
+    #[test]
+    fn rejects_fragments_out_of_their_session() {
+        let mut server = RustBustersServer::for_tests(38);
+        let (drone_send, drone_recv) = crossbeam_channel::unbounded();
+        server.packet_send.insert(2, drone_send);
+        let mut fragment = server
+            .disassemble_message(&register("rejected "), Encoding::Json, None)
+            .remove(0);
+        fragment.fragment_index = fragment.total_n_fragments;
+
+        server.handle_fragment(
+            fragment,
+            7,
+            SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![9, 2, 38],
+            },
+        );
+        let stats = serde_json::to_value(StatsManager::get_stats(38)).unwrap();
+        assert_eq!(stats["fragmentsRejected"], 1);
+        assert!(server.pending_received.is_empty());
+        // Rejected fragments are not acked
+        assert!(drone_recv.try_recv().is_err());
     }
 }
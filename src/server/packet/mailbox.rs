@@ -0,0 +1,244 @@
+use crate::server::db::{DbMessage, DeliveryState, OfflineMessage};
+use crate::{RustBustersServer, StatsManager};
+use chrono::Utc;
+use common_utils::{ClientToServerMessage, HostMessage, MessageBody, ServerToClientMessage};
+use log::{info, warn};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wg_2024::network::NodeId;
+
+/// Interval between two removals of the expired mailbox messages.
+const MAILBOX_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl RustBustersServer {
+    /// Stores a private message for a recipient that is not registered, to be delivered when it registers.
+    ///
+    /// ### Behavior
+    /// 1. If the mailbox of `dest_id` already holds `capacity` pending messages, the message is refused
+    ///    and a `SendingError` is sent back to `src_id`.
+    /// 2. Otherwise the message is saved in the `mailbox` table of the local database.
+    pub(crate) fn store_offline_message(
+        &mut self,
+        src_id: NodeId,
+        dest_id: NodeId,
+        message: &MessageBody,
+    ) {
+        let stored = match &self.db_manager {
+            Ok(db_manager) => match db_manager.count_pending_offline(dest_id) {
+                Ok(pending) if pending >= self.mailbox.capacity => {
+                    warn!(
+                        "Server {}: Mailbox of {} is full, refusing message from {}",
+                        self.id, dest_id, src_id
+                    );
+                    false
+                }
+                Ok(_) => db_manager.insert_offline(src_id, dest_id, message).is_ok(),
+                Err(err) => {
+                    warn!(
+                        "Server {}: Cannot read mailbox of {}: {}",
+                        self.id, dest_id, err
+                    );
+                    false
+                }
+            },
+            Err(_) => false,
+        };
+
+        if stored {
+            StatsManager::inc_offline_messages_stored(self.id);
+            info!(
+                "Server {}: Stored message from {} for offline user {}",
+                self.id, src_id, dest_id
+            );
+        } else {
+            self.send_network_message(
+                src_id,
+                HostMessage::FromServer(ServerToClientMessage::SendingError {
+                    error: "The recipient is offline and cannot receive more messages".to_string(),
+                    message: ClientToServerMessage::SendPrivateMessage {
+                        recipient_id: dest_id,
+                        message: message.clone(),
+                    },
+                }),
+            );
+        }
+    }
+
+    /// Delivers, in the order they were received, the messages stored for a user that just registered.
+    ///
+    /// Every message is saved with the other private messages, where its delivery state is tracked, and
+    /// stays pending in the mailbox until it is acked by the recipient, see `complete_mailbox_delivery()`.
+    /// Messages whose delivery is still in progress are not sent again. A message that cannot be saved is sent
+    /// once without tracking and marked as delivered right away.
+    pub(crate) fn deliver_offline_messages(&mut self, dest_id: NodeId) {
+        let pending = match &self.db_manager {
+            Ok(db_manager) => db_manager.get_pending_offline(dest_id).unwrap_or_default(),
+            Err(_) => return,
+        };
+
+        for offline_message in pending {
+            if self
+                .mailbox_deliveries
+                .values()
+                .any(|mailbox_id| *mailbox_id == offline_message.id)
+            {
+                continue;
+            }
+
+            let message = HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
+                sender_id: offline_message.src_id,
+                message: offline_message.body.clone(),
+            });
+            let Some(db_message_id) = self.store_mailbox_message(&offline_message) else {
+                // Without a stored copy its delivery cannot be tracked: the message is sent once and
+                // leaves the mailbox, instead of being sent again every time its recipient registers
+                warn!(
+                    "Server {}: Delivering mailbox message {} without tracking it",
+                    self.id, offline_message.id
+                );
+                self.send_network_message(dest_id, message);
+                self.mark_mailbox_delivered(&offline_message.id);
+                continue;
+            };
+            self.mailbox_deliveries
+                .insert(db_message_id, offline_message.id.clone());
+            self.send_tracked_message(dest_id, message, Some(db_message_id));
+        }
+    }
+
+    /// Completes the delivery of the mailbox message carried by a stored private message, if any.
+    ///
+    /// The mailbox message is marked as delivered once the private message is acked. If the private message
+    /// failed, it stays pending and is delivered again the next time its recipient registers.
+    pub(crate) fn complete_mailbox_delivery(
+        &mut self,
+        db_message_id: Uuid,
+        delivery_state: DeliveryState,
+    ) {
+        if !matches!(delivery_state, DeliveryState::Acked | DeliveryState::Failed) {
+            return;
+        }
+        let Some(mailbox_id) = self.mailbox_deliveries.remove(&db_message_id) else {
+            return;
+        };
+
+        if delivery_state == DeliveryState::Failed {
+            warn!(
+                "Server {}: Mailbox message {} not delivered, kept in the mailbox",
+                self.id, mailbox_id
+            );
+            return;
+        }
+        self.mark_mailbox_delivered(&mailbox_id);
+    }
+
+    /// Marks a mailbox message as delivered, so that it is not delivered again.
+    fn mark_mailbox_delivered(&self, mailbox_id: &str) {
+        if let Ok(db_manager) = &self.db_manager {
+            if let Err(err) = db_manager.mark_offline_delivered(mailbox_id) {
+                warn!(
+                    "Server {}: Cannot mark mailbox message {} as delivered: {}",
+                    self.id, mailbox_id, err
+                );
+            }
+        }
+        StatsManager::inc_offline_messages_delivered(self.id);
+    }
+
+    /// Saves a mailbox message with the other private messages, under the id of the mailbox message so
+    /// that delivering it again after a failure does not store it twice. It is replicated when first stored.
+    fn store_mailbox_message(&mut self, offline_message: &OfflineMessage) -> Option<Uuid> {
+        let db_message_id = Uuid::parse_str(&offline_message.id).ok()?;
+        let db_manager = self.db_manager.as_ref().ok()?;
+        let db_message = DbMessage::from_body(
+            offline_message.id.clone(),
+            offline_message.src_id,
+            offline_message.dest_id,
+            &offline_message.body,
+        );
+        match db_manager.insert_replica(&db_message) {
+            Ok(true) => {
+                self.replicate_message(&db_message);
+                self.send_db_message(db_message);
+            }
+            Ok(false) => {}
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot store mailbox message {}: {}",
+                    self.id, offline_message.id, err
+                );
+                return None;
+            }
+        }
+        Some(db_message_id)
+    }
+
+    /// Removes from the mailbox the messages older than the retention period.
+    ///
+    /// Runs at most once every `MAILBOX_PURGE_INTERVAL`.
+    pub(crate) fn purge_mailbox(&mut self) {
+        if self.last_mailbox_purge.elapsed() < MAILBOX_PURGE_INTERVAL {
+            return;
+        }
+        self.last_mailbox_purge = Instant::now();
+
+        if let Ok(db_manager) = &self.db_manager {
+            let retention = self.mailbox.retention.as_secs() as i64;
+            match db_manager.purge_offline(Utc::now().timestamp() - retention) {
+                Ok(0) => {}
+                Ok(removed) => info!(
+                    "Server {}: Removed {} expired mailbox messages",
+                    self.id, removed
+                ),
+                Err(err) => warn!("Server {}: Cannot purge mailbox: {}", self.id, err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_utils::MessageContent;
+
+    fn pending_count(server: &RustBustersServer, dest_id: NodeId) -> usize {
+        server
+            .db_manager
+            .as_ref()
+            .unwrap()
+            .count_pending_offline(dest_id)
+            .unwrap()
+    }
+
+    #[test]
+    fn marks_mailbox_delivered_only_once_acked() {
+        let mut server = RustBustersServer::for_tests(1);
+        let message = MessageBody {
+            sender_id: 2,
+            content: MessageContent::Text("while you were away".to_string()),
+            timestamp: "1700000000".to_string(),
+        };
+        server.store_offline_message(2, 3, &message);
+        assert_eq!(pending_count(&server, 3), 1);
+
+        // No route toward 3: the message is parked and stays pending in the mailbox
+        server.deliver_offline_messages(3);
+        assert_eq!(pending_count(&server, 3), 1);
+        let (&db_message_id, _) = server.mailbox_deliveries.iter().next().unwrap();
+
+        // A delivery in progress is not started again
+        server.deliver_offline_messages(3);
+        assert_eq!(server.mailbox_deliveries.len(), 1);
+        assert_eq!(server.unroutable[&3].len(), 1);
+
+        // A failed delivery keeps the message in the mailbox, to be delivered again
+        server.set_delivery_state(db_message_id, DeliveryState::Failed);
+        assert_eq!(pending_count(&server, 3), 1);
+        server.deliver_offline_messages(3);
+        assert!(server.mailbox_deliveries.contains_key(&db_message_id));
+
+        server.set_delivery_state(db_message_id, DeliveryState::Acked);
+        assert_eq!(pending_count(&server, 3), 0);
+        assert!(server.mailbox_deliveries.is_empty());
+    }
+}
@@ -1,8 +1,16 @@
 mod ack_handler;
+mod announcement;
 mod client_handler;
+mod delivery;
 mod flood_handler;
 mod fragment_handler;
+mod mailbox;
 mod nack_handler;
+mod outbound_queue;
+mod presence;
+mod registry;
+mod retransmission;
+mod room_handler;
 mod sender;
 
 use crate::RustBustersServer;
@@ -66,7 +74,12 @@ impl RustBustersServer {
                 // Handle Negative Acknowledgments
                 info!("Server {}: Received Nack {nack:?}", self.id);
                 StatsManager::inc_nacks_received(self.id);
-                self.handle_nack(packet.session_id, nack.fragment_index, nack.nack_type);
+                self.handle_nack(
+                    packet.session_id,
+                    nack.fragment_index,
+                    nack.nack_type,
+                    packet.routing_header.source(),
+                );
             }
         }
     }
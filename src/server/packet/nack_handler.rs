@@ -4,7 +4,7 @@ use crate::{RustBustersServer, StatsManager};
 use common_utils::HostEvent;
 use common_utils::{PacketHeader, PacketTypeHeader};
 use log::{info, warn};
-use wg_2024::network::SourceRoutingHeader;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::NackType;
 use wg_2024::packet::NackType::Dropped;
 use wg_2024::packet::{Fragment, NodeType, Packet, PacketType};
@@ -16,19 +16,26 @@ impl RustBustersServer {
     /// - `session_id: u64` – The session identifier to which the NACK belongs.
     /// - `fragment_index: u64` – The index of the fragment that was negatively acknowledged.
     /// - `nack_type: NackType` – The type of NACK, indicating the reason for failure.
+    /// - `nack_src: Option<NodeId>` – The node that generated the NACK.
     ///
     /// ### Behavior
     /// - If the fragment is found in `pending_sent`:
-    ///   - If `NackType::Dropped`, resends the fragment and updates statistics.
+    ///   - If `NackType::Dropped`, records the drop in the reliability estimate of the drone that sent the NACK,
+    ///     resends the fragment and updates statistics. A fragment of a session with forward error correction
+    ///     is not resent while the other fragments are enough to rebuild the message (see `absorb_fec_drop()`).
     ///   - If `NackType::ErrorInRouting`, removes the faulty node from `topology` and `known_node_types`,
-    ///     recalculates the route, and attempts to resend the fragment.
-    ///   - If `NackType::DestinationIsDrone` or `NackType::UnexpectedRecipient`, logs a warning.
+    ///     recalculates the route, attempts to resend the fragment and requests an early network discovery.
+    ///   - If `NackType::DestinationIsDrone`, drops the session through `fail_session()`.
+    ///   - If `NackType::UnexpectedRecipient`, logs a warning, requests an early network discovery and leaves
+    ///     the fragment to the retransmission timer.
+    /// - Every successful resend restarts the retransmission timer of the fragment, keeping its retry count.
     /// - If the fragment is unknown, logs a warning.
     pub(crate) fn handle_nack(
         &mut self,
         session_id: u64,
         fragment_index: u64,
         nack_type: NackType,
+        nack_src: Option<NodeId>,
     ) {
         match self
             .pending_sent
@@ -38,6 +45,14 @@ impl RustBustersServer {
             Some(mut packet) => {
                 match nack_type {
                     NackType::Dropped => {
+                        if let Some(drone_id) = nack_src {
+                            self.record_drop(drone_id, &packet.routing_header.hops);
+                        }
+                        // The parity fragments of the session may make the resend unnecessary
+                        if self.absorb_fec_drop(session_id, fragment_index) {
+                            return;
+                        }
+
                         info!("Server {}: Resending fragment {}", self.id, fragment_index);
                         // Resend the packet
                         if let Some(sender) = self.packet_send.get(&packet.routing_header.hops[1]) {
@@ -48,6 +63,7 @@ impl RustBustersServer {
                                 );
                             } else {
                                 StatsManager::inc_message_fragments_sent(self.id);
+                                self.restart_retransmission_timer(session_id, fragment_index);
 
                                 // Send MsgFragment to Simulation Controller
                                 self.send_to_sc(HostEvent::PacketSent(PacketHeader {
@@ -64,27 +80,23 @@ impl RustBustersServer {
                             self.id, fragment_index, nack_type
                         );
 
-                        // Updating topology
-                        self.topology.remove(&drone_id);
-                        self.topology.iter_mut().for_each(|(src_id, neighbors)| {
-                            neighbors.retain(|&dest_id| dest_id != drone_id);
-                        });
-                        // Removing from known nodes
-                        self.known_node_types.remove(&drone_id);
+                        // Updating topology and known nodes
+                        self.remove_node(drone_id);
+                        self.invalidate_route_cache();
 
                         // Calculating new route and resending fragment
                         let dest_id = *packet.routing_header.hops.last().expect("No destination");
-                        if let Some(route) = self.find_route(dest_id) {
+                        if let Some(route) = self.get_route(dest_id) {
                             packet.routing_header.hops = route.clone();
                             packet.routing_header.hop_index = 1;
                         } else {
                             warn!("Server {}: Error in finding route", self.id);
-                            self.launch_network_discovery();
-                            if let Some(route) = self.find_route(dest_id) {
-                                packet.routing_header.hops = route.clone();
-                                packet.routing_header.hop_index = 1;
-                            }
                         }
+                        self.request_discovery("error in routing");
+
+                        // Keep the rerouted packet for the following retransmissions
+                        self.pending_sent
+                            .insert((session_id, fragment_index), packet.clone());
 
                         // Resend the packet
                         if let Some(sender) = self.packet_send.get(&packet.routing_header.hops[1]) {
@@ -95,6 +107,7 @@ impl RustBustersServer {
                                 );
                             } else {
                                 StatsManager::inc_message_fragments_sent(self.id);
+                                self.restart_retransmission_timer(session_id, fragment_index);
 
                                 // Send MsgFragment to Simulation Controller
                                 self.send_to_sc(HostEvent::PacketSent(PacketHeader {
@@ -105,11 +118,21 @@ impl RustBustersServer {
                             }
                         }
                     }
-                    NackType::DestinationIsDrone | NackType::UnexpectedRecipient(_) => {
+                    NackType::DestinationIsDrone => {
+                        warn!(
+                            "Server {}: Nack for fragment {} with type {:?}",
+                            self.id, fragment_index, nack_type
+                        );
+                        // Resending the fragment cannot succeed
+                        self.fail_session(session_id, "Destination is a drone");
+                    }
+                    NackType::UnexpectedRecipient(_) => {
                         warn!(
                             "Server {}: Nack for fragment {} with type {:?}",
                             self.id, fragment_index, nack_type
                         );
+                        // The topology is out of date
+                        self.request_discovery("unexpected recipient");
                     }
                     _ => {}
                 }
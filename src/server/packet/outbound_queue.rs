@@ -0,0 +1,114 @@
+use crate::server::db::DeliveryState;
+use crate::server::peer::NetworkPayload;
+use crate::{RustBustersServer, StatsManager};
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::time::Instant;
+use uuid::Uuid;
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Parks a message toward a destination that currently has no known route.
+    ///
+    /// ### Parameters
+    /// - `destination_id: NodeId` – The unreachable destination.
+    /// - `message: NetworkPayload` – The message to be sent once a route is found.
+    /// - `db_message_id: Option<Uuid>` – The id of the stored private message carried by `message`, if any.
+    ///
+    /// ### Behavior
+    /// - Appends the message to the queue of `destination_id`.
+    /// - If the queue is full, drops its oldest message and reports it as failed.
+    /// - If the queue was empty, launches a network discovery to look for a route.
+    pub(crate) fn enqueue_unroutable(
+        &mut self,
+        destination_id: NodeId,
+        message: NetworkPayload,
+        db_message_id: Option<Uuid>,
+    ) {
+        let capacity = self.outbound_queue.capacity;
+        let queue = self.unroutable.entry(destination_id).or_default();
+        let first_parked = queue.is_empty();
+
+        queue.push_back((Instant::now(), message, db_message_id));
+        let overflow = if queue.len() > capacity {
+            queue.pop_front()
+        } else {
+            None
+        };
+
+        StatsManager::inc_messages_queued(self.id);
+        info!(
+            "Server {}: No route to {}, message parked until the destination becomes reachable",
+            self.id, destination_id
+        );
+
+        if let Some((_, dropped, dropped_db_message_id)) = overflow {
+            warn!(
+                "Server {}: Queue for {} is full, dropping the oldest message",
+                self.id, destination_id
+            );
+            if let Some(dropped_db_message_id) = dropped_db_message_id {
+                self.set_delivery_state(dropped_db_message_id, DeliveryState::Failed);
+            }
+            self.report_delivery_failure(destination_id, dropped, "Outbound queue full");
+        }
+
+        if first_parked {
+            self.request_discovery("destination unreachable");
+        }
+    }
+
+    /// Sends the parked messages whose destination became reachable.
+    ///
+    /// Messages are sent in the order they were parked. Destinations that are still unreachable keep their queue.
+    pub(crate) fn flush_unroutable(&mut self) {
+        let destinations: Vec<NodeId> = self.unroutable.keys().copied().collect();
+        for destination_id in destinations {
+            if self.get_route(destination_id).is_none() {
+                continue;
+            }
+
+            if let Some(queue) = self.unroutable.remove(&destination_id) {
+                info!(
+                    "Server {}: Route to {} found, flushing {} parked messages",
+                    self.id,
+                    destination_id,
+                    queue.len()
+                );
+                for (_, message, db_message_id) in queue {
+                    self.send_tracked_message(destination_id, message, db_message_id);
+                }
+            }
+        }
+    }
+
+    /// Drops the parked messages that waited longer than the configured deadline, reporting them as failed.
+    pub(crate) fn expire_unroutable(&mut self) {
+        let deadline = self.outbound_queue.deadline;
+        let mut expired = Vec::new();
+
+        for (destination_id, queue) in self.unroutable.iter_mut() {
+            while queue
+                .front()
+                .is_some_and(|(parked_at, _, _)| parked_at.elapsed() >= deadline)
+            {
+                if let Some((_, message, db_message_id)) = queue.pop_front() {
+                    expired.push((*destination_id, message, db_message_id));
+                }
+            }
+        }
+        self.unroutable.retain(|_, queue| !queue.is_empty());
+
+        for (destination_id, message, db_message_id) in expired {
+            warn!(
+                "Server {}: No route to {} found in time, dropping parked message",
+                self.id, destination_id
+            );
+            StatsManager::inc_queued_messages_expired(self.id);
+            if let Some(db_message_id) = db_message_id {
+                self.set_delivery_state(db_message_id, DeliveryState::Failed);
+            }
+            self.report_delivery_failure(destination_id, message, "No route to destination");
+        }
+    }
+}
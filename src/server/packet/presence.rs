@@ -0,0 +1,91 @@
+use crate::{RustBustersServer, StatsManager};
+use common_utils::{HostMessage, ServerToClientMessage};
+use log::info;
+use std::time::Instant;
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Records that a message was received from `src_id`, resetting its idle time.
+    pub(crate) fn touch_user(&mut self, src_id: NodeId) {
+        if self.active_users.contains_key(&src_id) {
+            self.last_activity.insert(src_id, Instant::now());
+        }
+    }
+
+    /// Unregisters the users that sent no message for longer than the idle timeout.
+    pub(crate) fn evict_idle_users(&mut self) {
+        let idle_timeout = self.presence.idle_timeout;
+        let idle: Vec<NodeId> = self
+            .active_users
+            .keys()
+            .filter(|id| {
+                self.last_activity
+                    .get(id)
+                    .map_or(true, |last| last.elapsed() >= idle_timeout)
+            })
+            .copied()
+            .collect();
+
+        for user_id in idle {
+            self.evict_user(user_id, "inactive");
+        }
+    }
+
+    /// Records the outcome of a session toward `dest_id`, evicting it after too many consecutive failures.
+    ///
+    /// ### Parameters
+    /// - `dest_id: NodeId` – The destination of the session.
+    /// - `delivered: bool` – Whether all the fragments of the session were acked.
+    pub(crate) fn record_session_outcome(&mut self, dest_id: NodeId, delivered: bool) {
+        if !self.active_users.contains_key(&dest_id) {
+            return;
+        }
+
+        if delivered {
+            self.delivery_failures.remove(&dest_id);
+            return;
+        }
+
+        let failures = self.delivery_failures.entry(dest_id).or_insert(0);
+        *failures += 1;
+        if *failures >= self.presence.max_delivery_failures {
+            self.evict_user(dest_id, "unreachable");
+        }
+    }
+
+    /// Removes a user from the active users and notifies the other active users.
+    ///
+    /// ### Behavior
+    /// 1. Removes `user_id` from the `active_users` map and records it in the user registry.
+    /// 2. Sends the active users through the Internal Channels to the UI.
+    /// 3. Sends `UserUnregistered` to the other active users.
+    pub(crate) fn remove_active_user(&mut self, user_id: NodeId) {
+        self.active_users.remove(&user_id);
+        self.last_activity.remove(&user_id);
+        self.delivery_failures.remove(&user_id);
+        self.record_unregistration(user_id);
+        self.publish_directory();
+
+        // Send active users to Internal Channels for retransmission to WebSoket client and finally to the UI
+        self.send_active_users();
+
+        // Cloning because of borrow checker issues
+        let other_users = self.active_users.clone();
+        other_users.iter().for_each(|(&other_id, _)| {
+            self.send_network_message(
+                other_id,
+                HostMessage::FromServer(ServerToClientMessage::UserUnregistered { id: user_id }),
+            );
+        });
+    }
+
+    /// Unregisters a user that did not unregister itself.
+    fn evict_user(&mut self, user_id: NodeId, reason: &str) {
+        info!(
+            "Server {}: Unregistering {} user {}",
+            self.id, reason, user_id
+        );
+        StatsManager::inc_users_evicted(self.id);
+        self.remove_active_user(user_id);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::RustBustersServer;
+use log::{info, warn};
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Restores the users that were registered when the server last stopped from the `users` table.
+    ///
+    /// Their sessions did not survive the restart: they are recorded as unregistered, their names stay
+    /// reserved, and the messages sent to them are kept in their mailbox until they register again.
+    pub(crate) fn restore_users(&self) {
+        let Ok(db_manager) = &self.db_manager else {
+            return;
+        };
+        let users = match db_manager.get_active_users() {
+            Ok(users) => users,
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot restore the registered users: {}",
+                    self.id, err
+                );
+                return;
+            }
+        };
+
+        if !users.is_empty() {
+            info!(
+                "Server {}: Reserved the names of {} users registered before the restart",
+                self.id,
+                users.len()
+            );
+        }
+        for user in users {
+            self.record_unregistration(user.node_id);
+        }
+    }
+
+    /// Checks whether `name` is used by a node other than `node_id`.
+    ///
+    /// A name is reserved to the node that registered it, even while that user is not registered.
+    pub(crate) fn is_name_reserved(&self, node_id: NodeId, name: &str) -> bool {
+        if self
+            .active_users
+            .iter()
+            .any(|(&id, n)| id != node_id && n == name)
+        {
+            return true;
+        }
+
+        match &self.db_manager {
+            Ok(db_manager) => match db_manager.get_user_by_name(name) {
+                Ok(user) => user.is_some_and(|user| user.node_id != node_id),
+                Err(err) => {
+                    warn!("Server {}: Cannot read the user registry: {}", self.id, err);
+                    false
+                }
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Checks whether `node_id` registered at least once, in this run or in a previous one.
+    pub(crate) fn is_known_user(&self, node_id: NodeId) -> bool {
+        match &self.db_manager {
+            Ok(db_manager) => matches!(db_manager.get_user(node_id), Ok(Some(_))),
+            Err(_) => false,
+        }
+    }
+
+    /// Records in the `users` table that `node_id` registered as `name`.
+    pub(crate) fn record_registration(&self, node_id: NodeId, name: &str) {
+        if let Ok(db_manager) = &self.db_manager {
+            if let Err(err) = db_manager.register_user(node_id, name) {
+                warn!(
+                    "Server {}: Cannot record the registration of {}: {}",
+                    self.id, node_id, err
+                );
+            }
+        }
+    }
+
+    /// Records in the `users` table that `node_id` unregistered.
+    pub(crate) fn record_unregistration(&self, node_id: NodeId) {
+        if let Ok(db_manager) = &self.db_manager {
+            if let Err(err) = db_manager.unregister_user(node_id) {
+                warn!(
+                    "Server {}: Cannot record the unregistration of {}: {}",
+                    self.id, node_id, err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restored_users_must_register_again() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.record_registration(2, "alice");
+
+        server.restore_users();
+        assert!(server.active_users.is_empty());
+        assert!(server.is_name_reserved(3, "alice"));
+        assert!(!server.is_name_reserved(2, "alice"));
+
+        server.handle_register_user(2, "alice");
+        assert_eq!(
+            server.active_users.get(&2).map(String::as_str),
+            Some("alice")
+        );
+    }
+}
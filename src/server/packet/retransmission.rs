@@ -0,0 +1,229 @@
+use crate::server::db::DeliveryState;
+use crate::server::peer::{NetworkPayload, PeerMessage};
+use crate::{RustBustersServer, StatsManager};
+use common_utils::{
+    ClientToServerMessage, HostEvent, HostMessage, PacketHeader, PacketTypeHeader,
+    ServerToClientMessage,
+};
+use log::{info, warn};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Arms (or re-arms) the retransmission timer of a sent fragment.
+    ///
+    /// ### Parameters
+    /// - `session_id: u64` – The session the fragment belongs to.
+    /// - `fragment_index: u64` – The index of the fragment within the session.
+    /// - `retries: u32` – The number of retransmissions already performed for the fragment.
+    pub(crate) fn arm_retransmission_timer(
+        &mut self,
+        session_id: u64,
+        fragment_index: u64,
+        retries: u32,
+    ) {
+        let deadline = Instant::now() + self.retransmission.timeout(retries);
+        self.retransmission_timers
+            .insert((session_id, fragment_index), (deadline, retries));
+    }
+
+    /// Restarts the retransmission timer of a fragment that was resent outside of the timer,
+    /// e.g. after a Nack, without consuming its retry budget.
+    pub(crate) fn restart_retransmission_timer(&mut self, session_id: u64, fragment_index: u64) {
+        let retries = self
+            .retransmission_timers
+            .get(&(session_id, fragment_index))
+            .map_or(0, |(_, retries)| *retries);
+        self.arm_retransmission_timer(session_id, fragment_index, retries);
+    }
+
+    /// Returns how long the network listener can block before the next retransmission timer expires.
+    ///
+    /// The value is capped at one second so that the periodic tasks of the listener keep running.
+    pub(crate) fn next_timer_timeout(&self) -> Duration {
+        let max_wait = Duration::from_millis(1000);
+        let now = Instant::now();
+        self.retransmission_timers
+            .values()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now))
+            .min()
+            .map_or(max_wait, |wait| {
+                wait.clamp(Duration::from_millis(10), max_wait)
+            })
+    }
+
+    /// Retransmits the fragments whose timer expired.
+    ///
+    /// ### Behavior
+    /// - Collects every `(session_id, fragment_index)` whose deadline is in the past.
+    /// - If the fragment already used its retry budget, the whole session is dropped through `fail_session()`.
+    /// - Otherwise the fragment is sent again to the first hop of its route and the timer is re-armed
+    ///   with an exponentially increased timeout.
+    pub(crate) fn check_retransmissions(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<((u64, u64), u32)> = self
+            .retransmission_timers
+            .iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(key, (_, retries))| (*key, *retries))
+            .collect();
+
+        for ((session_id, fragment_index), retries) in expired {
+            // The session may have been dropped while handling a previous expired fragment
+            if !self
+                .retransmission_timers
+                .contains_key(&(session_id, fragment_index))
+            {
+                continue;
+            }
+
+            if retries >= self.retransmission.max_retries {
+                warn!(
+                    "Server {}: Fragment {} of session {} not acked after {} retries",
+                    self.id, fragment_index, session_id, retries
+                );
+                self.fail_session(session_id, "Retry budget exhausted");
+                continue;
+            }
+
+            info!(
+                "Server {}: Retransmitting fragment {} of session {} (retry {})",
+                self.id,
+                fragment_index,
+                session_id,
+                retries + 1
+            );
+            self.resend_fragment(session_id, fragment_index);
+            self.arm_retransmission_timer(session_id, fragment_index, retries + 1);
+        }
+    }
+
+    /// Sends again a fragment stored in `pending_sent` to the first hop of its route.
+    fn resend_fragment(&mut self, session_id: u64, fragment_index: u64) {
+        let Some(packet) = self
+            .pending_sent
+            .get(&(session_id, fragment_index))
+            .cloned()
+        else {
+            self.retransmission_timers
+                .remove(&(session_id, fragment_index));
+            return;
+        };
+
+        let next_hop = packet.routing_header.hops[1];
+        if let Some(sender) = self.packet_send.get(&next_hop) {
+            if let Err(err) = sender.send(packet.clone()) {
+                warn!(
+                    "Server {}: Unable to retransmit fragment {}: {}",
+                    self.id, fragment_index, err
+                );
+            } else {
+                StatsManager::inc_message_fragments_sent(self.id);
+                StatsManager::inc_fragments_retransmitted(self.id);
+
+                // Send MsgFragment to Simulation Controller
+                self.send_to_sc(HostEvent::PacketSent(PacketHeader {
+                    session_id,
+                    pack_type: PacketTypeHeader::MsgFragment,
+                    routing_header: packet.routing_header.clone(),
+                }));
+            }
+        } else {
+            warn!(
+                "Server {}: Cannot retransmit fragment {} to {}",
+                self.id, fragment_index, next_hop
+            );
+        }
+    }
+
+    /// Drops a session whose fragments cannot be delivered.
+    ///
+    /// ### Parameters
+    /// - `session_id: u64` – The session to be dropped.
+    /// - `reason: &str` – A human readable description of the failure.
+    ///
+    /// ### Behavior
+    /// 1. Removes every fragment of the session from `pending_sent`, its retransmission timers and its
+    ///    forward error correction state.
+    /// 2. Removes the session from `sessions_info`, marks the stored message it carries as failed and
+    ///    reports the failure through `report_delivery_failure()`.
+    /// 3. Counts the failure toward the destination, which is unregistered after too many of them.
+    pub(crate) fn fail_session(&mut self, session_id: u64, reason: &str) {
+        self.fec_sessions.remove(&session_id);
+        self.pending_sent.retain(|(id, _), _| *id != session_id);
+        self.retransmission_timers
+            .retain(|(id, _), _| *id != session_id);
+
+        if let Some((dest_id, _, payload)) = self.sessions_info.remove(&session_id) {
+            warn!(
+                "Server {}: Dropping session {} to {}: {}",
+                self.id, session_id, dest_id, reason
+            );
+            StatsManager::inc_sessions_failed(self.id);
+            self.complete_session_delivery(session_id, DeliveryState::Failed);
+            self.report_delivery_failure(dest_id, payload, reason);
+            self.record_session_outcome(dest_id, false);
+        }
+    }
+
+    /// Reports a message that could not be delivered to the simulation controller and,
+    /// if the message was a private message forwarded on behalf of a client, to its sender.
+    ///
+    /// Messages for other servers are not reported to the simulation controller: a relayed
+    /// private message that cannot reach the next server is failed through `fail_relay()`, and
+    /// replicated messages are sent again once the server is reachable.
+    pub(crate) fn report_delivery_failure(
+        &mut self,
+        dest_id: NodeId,
+        payload: NetworkPayload,
+        reason: &str,
+    ) {
+        match payload {
+            NetworkPayload::Host(host_message) => {
+                self.notify_sending_failure(dest_id, &host_message);
+                self.send_to_sc(HostEvent::HostMessageFailed(
+                    dest_id,
+                    host_message,
+                    reason.to_string(),
+                ));
+            }
+            NetworkPayload::Server(PeerMessage::Relay {
+                relay_id,
+                origin_server,
+                ..
+            }) => self.fail_relay(&relay_id, origin_server),
+            NetworkPayload::Server(PeerMessage::Replicate { messages }) => {
+                self.record_missed_replicas(dest_id, &messages)
+            }
+            NetworkPayload::Server(_) => warn!(
+                "Server {}: Cannot deliver message to server {}: {}",
+                self.id, dest_id, reason
+            ),
+        }
+    }
+
+    /// Notifies the original sender of a private message that it could not be delivered.
+    ///
+    /// Only messages forwarded on behalf of a registered client are reported, so that a failure
+    /// in delivering the notification itself never generates a new notification.
+    pub(crate) fn notify_sending_failure(&mut self, dest_id: NodeId, host_message: &HostMessage) {
+        if let HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
+            sender_id,
+            message,
+        }) = host_message
+        {
+            if *sender_id != self.id && self.active_users.contains_key(sender_id) {
+                self.send_network_message(
+                    *sender_id,
+                    HostMessage::FromServer(ServerToClientMessage::SendingError {
+                        error: "Failed to deliver message! Retry in a few seconds".to_string(),
+                        message: ClientToServerMessage::SendPrivateMessage {
+                            recipient_id: dest_id,
+                            message: message.clone(),
+                        },
+                    }),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{InternalChannelsManager, RustBustersServer};
+use common_utils::{HostMessage, MessageBody, ServerToClientMessage};
+use log::{info, warn};
+use wg_2024::network::NodeId;
+
+/// Maximum length of a room name
+const MAX_ROOM_NAME_LEN: usize = 32;
+
+impl RustBustersServer {
+    /// Handles the creation of a room
+    ///
+    /// ### Behavior
+    /// 1. Verifies that `src_id` is registered and that the name is valid and not taken.
+    /// 2. Saves the room in the local database with `src_id` as its owner and first member.
+    /// 3. Sends `RoomJoined` back and pushes the updated rooms to the UI.
+    pub(crate) fn handle_create_room(&mut self, src_id: NodeId, room: &str) {
+        let Some(room) = self.check_room_request(src_id, room) else {
+            return;
+        };
+
+        let Ok(db_manager) = &self.db_manager else {
+            self.send_room_failure(src_id, room, "Rooms are not available");
+            return;
+        };
+        match db_manager.create_room(room, src_id) {
+            Ok(true) => {
+                info!("Server {}: {} created room {}", self.id, src_id, room);
+                self.send_network_message(
+                    src_id,
+                    HostMessage::FromServer(ServerToClientMessage::RoomJoined {
+                        room: room.to_string(),
+                        members: vec![src_id],
+                    }),
+                );
+                self.send_rooms();
+            }
+            Ok(false) => self.send_room_failure(src_id, room, "Room already exists"),
+            Err(err) => {
+                warn!("Server {}: Cannot create room {}: {}", self.id, room, err);
+                self.send_room_failure(src_id, room, "Rooms are not available");
+            }
+        }
+    }
+
+    /// Handles a user joining a room
+    ///
+    /// ### Behavior
+    /// 1. Verifies that `src_id` is registered, that the name is valid and that the room exists.
+    /// 2. Adds `src_id` to the members of the room in the local database.
+    /// 3. Sends `RoomJoined` with the members back and `UserJoinedRoom` to the other active members.
+    pub(crate) fn handle_join_room(&mut self, src_id: NodeId, room: &str) {
+        let Some(room) = self.check_room_request(src_id, room) else {
+            return;
+        };
+
+        let Ok(db_manager) = &self.db_manager else {
+            self.send_room_failure(src_id, room, "Rooms are not available");
+            return;
+        };
+        let joined = match db_manager.room_exists(room) {
+            Ok(true) => db_manager.add_room_member(room, src_id),
+            Ok(false) => {
+                self.send_room_failure(src_id, room, "Room not found");
+                return;
+            }
+            Err(err) => Err(err),
+        };
+
+        match joined {
+            Ok(true) => {
+                let members = self.get_room_members(room);
+                self.send_network_message(
+                    src_id,
+                    HostMessage::FromServer(ServerToClientMessage::RoomJoined {
+                        room: room.to_string(),
+                        members: members.clone(),
+                    }),
+                );
+                self.broadcast_to_room(
+                    &members,
+                    src_id,
+                    ServerToClientMessage::UserJoinedRoom {
+                        room: room.to_string(),
+                        user_id: src_id,
+                    },
+                );
+                self.send_rooms();
+            }
+            Ok(false) => self.send_room_failure(src_id, room, "You are already a member"),
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot add {} to room {}: {}",
+                    self.id, src_id, room, err
+                );
+                self.send_room_failure(src_id, room, "Rooms are not available");
+            }
+        }
+    }
+
+    /// Handles a user leaving a room
+    ///
+    /// ### Behavior
+    /// 1. Verifies that `src_id` is registered and that the name is valid.
+    /// 2. Removes `src_id` from the members of the room in the local database.
+    /// 3. Sends `RoomLeft` back and `UserLeftRoom` to the other active members.
+    pub(crate) fn handle_leave_room(&mut self, src_id: NodeId, room: &str) {
+        let Some(room) = self.check_room_request(src_id, room) else {
+            return;
+        };
+
+        let left = match &self.db_manager {
+            Ok(db_manager) => db_manager.remove_room_member(room, src_id),
+            Err(_) => Ok(false),
+        };
+
+        match left {
+            Ok(true) => {
+                self.send_network_message(
+                    src_id,
+                    HostMessage::FromServer(ServerToClientMessage::RoomLeft {
+                        room: room.to_string(),
+                    }),
+                );
+                let members = self.get_room_members(room);
+                self.broadcast_to_room(
+                    &members,
+                    src_id,
+                    ServerToClientMessage::UserLeftRoom {
+                        room: room.to_string(),
+                        user_id: src_id,
+                    },
+                );
+                self.send_rooms();
+            }
+            Ok(false) => self.send_room_failure(src_id, room, "You are not a member"),
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot remove {} from room {}: {}",
+                    self.id, src_id, room, err
+                );
+                self.send_room_failure(src_id, room, "Rooms are not available");
+            }
+        }
+    }
+
+    /// Handles a message posted to a room
+    ///
+    /// ### Behavior
+    /// 1. Verifies that `src_id` is registered, that the name is valid and that `src_id` is a member of the room.
+    /// 2. Sends a `RoomMessage` to every other member that is currently registered.
+    pub(crate) fn handle_send_room_message(
+        &mut self,
+        src_id: NodeId,
+        room: &str,
+        message: &MessageBody,
+    ) {
+        let Some(room) = self.check_room_request(src_id, room) else {
+            return;
+        };
+
+        let members = self.get_room_members(room);
+        if !members.contains(&src_id) {
+            self.send_room_failure(src_id, room, "You are not a member");
+            return;
+        }
+
+        self.broadcast_to_room(
+            &members,
+            src_id,
+            ServerToClientMessage::RoomMessage {
+                room: room.to_string(),
+                sender_id: src_id,
+                message: message.clone(),
+            },
+        );
+    }
+
+    /// Handles request for the list of rooms
+    pub(crate) fn handle_request_rooms(&mut self, src_id: NodeId) {
+        let rooms = match &self.db_manager {
+            Ok(db_manager) => db_manager.get_rooms().unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        self.send_network_message(
+            src_id,
+            HostMessage::FromServer(ServerToClientMessage::RoomsList {
+                rooms: rooms.into_iter().map(|room| room.name).collect(),
+            }),
+        );
+    }
+
+    /// Sends the rooms with their members through the Internal Channels to the UI
+    pub(crate) fn send_rooms(&self) {
+        if let Ok(db_manager) = &self.db_manager {
+            if let Ok(rooms) = db_manager.get_rooms() {
+                InternalChannelsManager::send_rooms(self.id, rooms);
+            }
+        }
+    }
+
+    /// Retrieves the members of a room from the local database, empty if the room does not exist
+    fn get_room_members(&self, room: &str) -> Vec<NodeId> {
+        match &self.db_manager {
+            Ok(db_manager) => db_manager.get_room_members(room).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Sends a message to the registered members of a room, except `src_id`
+    fn broadcast_to_room(
+        &mut self,
+        members: &[NodeId],
+        src_id: NodeId,
+        message: ServerToClientMessage,
+    ) {
+        for &member_id in members {
+            if member_id != src_id && self.active_users.contains_key(&member_id) {
+                self.send_network_message(member_id, HostMessage::FromServer(message.clone()));
+            }
+        }
+    }
+
+    /// Verifies that `src_id` is registered and that the room name is valid, answering with the failure otherwise.
+    ///
+    /// ### Returns
+    /// The room name without its surrounding whitespace, so that every request refers to a room by the
+    /// name it was created with.
+    fn check_room_request<'a>(&mut self, src_id: NodeId, room: &'a str) -> Option<&'a str> {
+        if !self.check_room_user(src_id) {
+            return None;
+        }
+
+        let room = room.trim();
+        if room.is_empty() || room.len() > MAX_ROOM_NAME_LEN {
+            self.send_room_failure(src_id, room, "Invalid room name");
+            return None;
+        }
+        Some(room)
+    }
+
+    /// Verifies that `src_id` is registered, sending `UserNotFound` back otherwise
+    fn check_room_user(&mut self, src_id: NodeId) -> bool {
+        if self.active_users.contains_key(&src_id) {
+            return true;
+        }
+        self.send_network_message(
+            src_id,
+            HostMessage::FromServer(ServerToClientMessage::UserNotFound { user_id: src_id }),
+        );
+        false
+    }
+
+    fn send_room_failure(&mut self, src_id: NodeId, room: &str, reason: &str) {
+        self.send_network_message(
+            src_id,
+            HostMessage::FromServer(ServerToClientMessage::RoomFailure {
+                room: room.to_string(),
+                reason: reason.to_string(),
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_utils::MessageContent;
+
+    #[test]
+    fn refers_to_rooms_by_their_trimmed_name() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.active_users.insert(2, "alice".to_string());
+        server.active_users.insert(3, "bob".to_string());
+
+        server.handle_create_room(2, "  lobby ");
+        server.handle_join_room(3, "lobby\t");
+        assert_eq!(server.get_room_members("lobby"), vec![2, 3]);
+
+        let parked = server.unroutable.get(&2).map_or(0, |queue| queue.len());
+        server.handle_send_room_message(
+            3,
+            " lobby",
+            &MessageBody {
+                sender_id: 3,
+                content: MessageContent::Text("hello".to_string()),
+                timestamp: "1700000000".to_string(),
+            },
+        );
+        // Without a route toward 2, the room message is parked for it
+        assert_eq!(server.unroutable[&2].len(), parked + 1);
+
+        server.handle_leave_room(3, " lobby ");
+        assert_eq!(server.get_room_members("lobby"), vec![2]);
+
+        server.handle_join_room(3, &"x".repeat(MAX_ROOM_NAME_LEN + 1));
+        assert_eq!(server.get_room_members("lobby"), vec![2]);
+    }
+}
@@ -12,30 +12,75 @@ use common_utils::{HostMessage, PacketHeader, ServerToClientMessage, User};
 
 use std::collections::HashSet;
 
+use crate::server::ad::codec::Encoding;
+use crate::server::ad::fec::{self, FecSession};
+use crate::server::db::DeliveryState;
+use crate::server::peer::NetworkPayload;
 use crate::RustBustersServer;
+use uuid::Uuid;
 
 impl RustBustersServer {
+    /// Sends a network message that does not carry a stored private message.
+    ///
+    /// See `send_tracked_message()`.
+    pub(crate) fn send_network_message(&mut self, destination_id: NodeId, message: HostMessage) {
+        self.send_tracked_message(destination_id, message, None);
+    }
+
+    /// Sends a network message with the encoding understood by its destination, see `encoding_for()`.
+    ///
+    /// See `send_encoded_message()`.
+    pub(crate) fn send_tracked_message(
+        &mut self,
+        destination_id: NodeId,
+        message: impl Into<NetworkPayload>,
+        db_message_id: Option<Uuid>,
+    ) {
+        let encoding = self.encoding_for(destination_id);
+        self.send_encoded_message(destination_id, message, db_message_id, encoding);
+    }
+
     /// Sends a network message to a specified destination by fragmenting and routing it through the network.
     ///
     /// ### Parameters
     /// - `destination_id: NodeId` – The identifier of the destination node to send the message to.
-    /// - `message: HostMessage` – The message to be sent, which will be fragmented.
+    /// - `message: impl Into<NetworkPayload>` – The message to be sent, which will be fragmented:
+    ///   a `HostMessage` for a client or a `PeerMessage` for another server.
+    /// - `db_message_id: Option<Uuid>` – The id of the stored private message carried by `message`, whose
+    ///   delivery state follows the session: `Sent` once the fragments are sent, then `Acked` or `Failed`.
+    /// - `encoding: Encoding` – The encoding of the session. The receiver detects it from the reassembled bytes.
     ///
     /// ### Behavior
-    /// - Attempts to find a route to the destination node using `find_route()`.
+    /// - Attempts to find a route to the destination node using `get_route()`.
     /// - If a route is found:
-    ///   - The message is fragmented into smaller units using `disassemble_message()`.
+    ///   - The message is encoded with `encoding` and fragmented into smaller units
+    ///     using `disassemble_message()`, with parity fragments when the route is lossy (see `fec_loss_rate()`).
+    ///     Such a session is tracked in `fec_sessions`, since it completes once enough of its fragments are acked.
     ///   - A new session is created, and the session information (destination, timestamp, and message) is stored.
     ///   - Each fragment is sent along the route in sequential hops, starting with the first hop.
-    ///   - Updates the `pending_sent` map to track the fragments that need acknowledgment.
     ///   - Sends the packet to the next hop and handles potential sending errors by logging warnings.
+    ///   - Updates the `pending_sent` map to track the fragments that need acknowledgment and arms
+    ///     their retransmission timer, so that lost fragments are sent again.
     ///   - Updates statistics to track the number of fragments sent and the total messages sent.
     ///   - Sends an update to the Simulation Controller (SC) for each fragment sent.
-    pub(crate) fn send_network_message(&mut self, destination_id: NodeId, message: HostMessage) {
+    /// - If no route is found, the message is parked with `enqueue_unroutable()` until the destination becomes reachable,
+    ///   then sent with the encoding understood by the destination.
+    pub(crate) fn send_encoded_message(
+        &mut self,
+        destination_id: NodeId,
+        message: impl Into<NetworkPayload>,
+        db_message_id: Option<Uuid>,
+        encoding: Encoding,
+    ) {
+        let message = message.into();
         // Find route to destination
-        if let Some(route) = self.find_route(destination_id) {
+        if let Some(route) = self.get_route(destination_id) {
             // Disassemble the message
-            let fragments = self.disassemble_message(&message);
+            let fragments = self.disassemble_message(
+                &message,
+                encoding,
+                self.fec_loss_rate(destination_id, &route),
+            );
 
             self.session_id_counter += 1;
             let session_id = self.session_id_counter;
@@ -45,6 +90,23 @@ impl RustBustersServer {
                 session_id,
                 (destination_id, Instant::now(), message.clone()),
             );
+            if let Some(db_message_id) = db_message_id {
+                self.session_messages.insert(session_id, db_message_id);
+            }
+            if let Some(fragment) = fragments.first().filter(|f| fec::is_fec_fragment(f)) {
+                let fec_session = FecSession {
+                    data_fragments: fec::data_fragments(fragment),
+                    total_fragments: fragment.total_n_fragments,
+                    acked: 0,
+                    dropped: 0,
+                };
+                StatsManager::inc_fec_sessions_sent(self.id);
+                StatsManager::add_parity_fragments_sent(
+                    self.id,
+                    fec_session.total_fragments - fec_session.data_fragments,
+                );
+                self.fec_sessions.insert(session_id, fec_session);
+            }
 
             // Send the fragments along the route
             for fragment in fragments {
@@ -67,24 +129,13 @@ impl RustBustersServer {
                 let next_hop = packet.routing_header.hops[1];
                 if let Some(sender) = self.packet_send.get(&next_hop) {
                     if let Err(e) = sender.send(packet.clone()) {
+                        // The retransmission timer will take care of sending it again
                         warn!(
                             "Server {}: Failed to send packet to {}: {:?}",
                             self.id, next_hop, e
                         );
-                        let error_msg = ServerToClientMessage::SendingError {
-                            error: "Failed to send message! Retry in a few seconds".to_string(),
-                            message: match message.clone() {
-                                HostMessage::FromClient(client_msg) => client_msg,
-                                _ => return,
-                            },
-                        };
                     }
 
-                    // Update pending fragments that are waiting to be acked
-                    self.pending_sent
-                        .entry((session_id, fragment_index))
-                        .or_insert(packet);
-
                     // Update stats
                     StatsManager::inc_message_fragments_sent(self.id);
 
@@ -94,12 +145,24 @@ impl RustBustersServer {
                         session_id,
                         pack_type: PacketTypeHeader::MsgFragment,
                     }));
+                } else {
+                    warn!("Server {}: Cannot send packet to {}", self.id, next_hop);
                 }
+
+                // Update pending fragments that are waiting to be acked and arm their retransmission timer
+                self.pending_sent
+                    .entry((session_id, fragment_index))
+                    .or_insert(packet);
+                self.arm_retransmission_timer(session_id, fragment_index, 0);
             }
 
             // Update stats
             StatsManager::inc_messages_sent(self.id);
 
+            if let Some(db_message_id) = db_message_id {
+                self.set_delivery_state(db_message_id, DeliveryState::Sent);
+            }
+
             info!(
                 "Server {}: Sent message to {} via route {:?}",
                 self.id, destination_id, route
@@ -109,6 +172,7 @@ impl RustBustersServer {
                 "Server {}: Unable to find route to {}",
                 self.id, destination_id
             );
+            self.enqueue_unroutable(destination_id, message, db_message_id);
         }
     }
 }
@@ -0,0 +1,136 @@
+use crate::server::ad::codec::Encoding;
+use crate::server::peer::PeerMessage;
+use crate::RustBustersServer;
+use common_utils::User;
+use log::info;
+use wg_2024::network::NodeId;
+use wg_2024::packet::NodeType;
+
+impl RustBustersServer {
+    /// Returns the other servers found by the network discovery.
+    pub(crate) fn peer_servers(&self) -> Vec<NodeId> {
+        let mut servers: Vec<NodeId> = self
+            .known_node_types
+            .iter()
+            .filter(|(&id, node_type)| id != self.id && **node_type == NodeType::Server)
+            .map(|(&id, _)| id)
+            .collect();
+        servers.sort_unstable();
+        servers
+    }
+
+    /// Sends the directory of the local users to the servers found since the last exchange,
+    /// asking them for their own directory.
+    pub(crate) fn exchange_directories(&mut self) {
+        for server_id in self.peer_servers() {
+            if self.directory_peers.insert(server_id) {
+                info!(
+                    "Server {}: Exchanging user directory with server {}",
+                    self.id, server_id
+                );
+                self.send_directory(server_id, true);
+            }
+        }
+    }
+
+    /// Sends the updated directory of the local users to the servers it was exchanged with.
+    pub(crate) fn publish_directory(&mut self) {
+        let peers: Vec<NodeId> = self.directory_peers.iter().copied().collect();
+        for server_id in peers {
+            self.send_directory(server_id, false);
+        }
+    }
+
+    /// Handles the directory of the users registered on another server
+    ///
+    /// ### Behavior
+    /// 1. Replaces the users previously known on `server_id` with the received ones.
+    ///    Users that are registered locally are ignored.
+    /// 2. Records whether `server_id` decodes binary encoded messages, see `encoding_for()`.
+    /// 3. If `reply` is set, sends back the directory of the local users.
+    pub(crate) fn handle_directory(
+        &mut self,
+        server_id: NodeId,
+        users: Vec<(NodeId, String)>,
+        reply: bool,
+        binary: bool,
+    ) {
+        self.remote_users
+            .retain(|_, (remote_server, _)| *remote_server != server_id);
+        for (user_id, name) in users {
+            if !self.active_users.contains_key(&user_id) {
+                self.remote_users.insert(user_id, (server_id, name));
+            }
+        }
+        self.directory_peers.insert(server_id);
+        if binary {
+            self.binary_peers.insert(server_id);
+        } else {
+            self.binary_peers.remove(&server_id);
+        }
+
+        if reply {
+            self.send_directory(server_id, false);
+        }
+    }
+
+    /// Returns the users registered on this server and on the other servers.
+    pub(crate) fn get_reachable_users(&self) -> Vec<User> {
+        let mut users = self.get_active_users();
+        users.extend(
+            self.remote_users
+                .iter()
+                .map(|(&id, (_, name))| User::new(id, name.clone())),
+        );
+        users
+    }
+
+    /// Returns the server on which `user_id` is registered, if it is registered on another server.
+    pub(crate) fn remote_user_server(&self, user_id: NodeId) -> Option<NodeId> {
+        self.remote_users
+            .get(&user_id)
+            .map(|(server_id, _)| *server_id)
+    }
+
+    fn send_directory(&mut self, server_id: NodeId, reply: bool) {
+        let users = self
+            .active_users
+            .iter()
+            .map(|(&id, name)| (id, name.clone()))
+            .collect();
+        // The directory advertises the encodings of this server, so it is always readable as JSON
+        self.send_encoded_message(
+            server_id,
+            PeerMessage::Directory {
+                users,
+                reply,
+                binary: true,
+            },
+            None,
+            Encoding::Json,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_binary_only_toward_servers_that_advertised_it() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.peer_encoding = Encoding::Binary;
+        server.known_node_types.insert(5, NodeType::Server);
+        server.known_node_types.insert(6, NodeType::Client);
+        assert_eq!(server.encoding_for(5), Encoding::Json);
+
+        server.handle_directory(5, vec![(7, "carol".to_string())], false, true);
+        assert_eq!(server.encoding_for(5), Encoding::Binary);
+        assert_eq!(server.encoding_for(6), Encoding::Json);
+        assert_eq!(server.remote_user_server(7), Some(5));
+
+        // A server restarted with an older version stops advertising it
+        server.handle_directory(5, Vec::new(), false, false);
+        assert_eq!(server.encoding_for(5), Encoding::Json);
+    }
+}
@@ -0,0 +1,61 @@
+pub mod directory;
+pub mod relay;
+pub mod replication;
+
+use crate::server::db::DbMessage;
+use common_utils::{HostMessage, MessageBody};
+use serde::{Deserialize, Serialize};
+use wg_2024::network::NodeId;
+
+/// Content of a session exchanged over the drone network.
+///
+/// The variants are untagged so that a `Host` payload is serialized exactly as the `HostMessage`
+/// it wraps: clients keep receiving and sending plain `HostMessage`s, only servers exchange `Server` payloads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NetworkPayload {
+    Host(HostMessage),
+    Server(PeerMessage),
+}
+
+impl From<HostMessage> for NetworkPayload {
+    fn from(message: HostMessage) -> Self {
+        NetworkPayload::Host(message)
+    }
+}
+
+impl From<PeerMessage> for NetworkPayload {
+    fn from(message: PeerMessage) -> Self {
+        NetworkPayload::Server(message)
+    }
+}
+
+/// Messages exchanged between the servers of the network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PeerMessage {
+    /// The users registered on the sending server. If `reply` is set the receiver answers with its own directory.
+    /// `binary` advertises that the sending server decodes `Encoding::Binary` messages.
+    Directory {
+        users: Vec<(NodeId, String)>,
+        reply: bool,
+        #[serde(default)]
+        binary: bool,
+    },
+    /// A private message for a user registered on another server.
+    /// `visited` holds the servers the message went through, starting from `origin_server`.
+    /// `message_id` is the id under which the origin server stored the message, so that every
+    /// server stores it once under the same id.
+    Relay {
+        relay_id: String,
+        origin_server: NodeId,
+        message_id: String,
+        sender_id: NodeId,
+        recipient_id: NodeId,
+        message: MessageBody,
+        visited: Vec<NodeId>,
+    },
+    /// Sent back to the origin server of a relayed message once it is handed to its recipient, or dropped.
+    RelayAck { relay_id: String, delivered: bool },
+    /// Copies of messages stored by the sending server, to be stored unless already present.
+    Replicate { messages: Vec<DbMessage> },
+}
@@ -0,0 +1,388 @@
+use crate::server::db::{DbMessage, DeliveryState};
+use crate::server::peer::PeerMessage;
+use crate::{RustBustersServer, StatsManager};
+use common_utils::{HostMessage, MessageBody, ServerToClientMessage};
+use log::{info, warn};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wg_2024::network::NodeId;
+use wg_2024::packet::NodeType;
+
+/// Maximum number of servers a relayed message can go through, the origin server included.
+const MAX_RELAY_HOPS: usize = 4;
+
+/// Time the origin server waits for the `RelayAck` of a relayed message before considering it failed.
+const RELAY_ACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time the id of a relayed message is remembered, to drop the copies received more than once.
+const SEEN_RELAY_RETENTION: Duration = Duration::from_secs(5 * 60);
+
+/// A private message relayed to another server, waiting for its `RelayAck`.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingRelay {
+    pub(crate) sender_id: NodeId,
+    pub(crate) recipient_id: NodeId,
+    pub(crate) message: MessageBody,
+    pub(crate) db_message_id: Option<Uuid>,
+    pub(crate) sent_at: Instant,
+}
+
+impl RustBustersServer {
+    /// Handles a message received from another server.
+    ///
+    /// Messages whose source is not a known server are dropped.
+    pub(crate) fn handle_peer_message(&mut self, src_id: NodeId, message: PeerMessage) {
+        if self.known_node_types.get(&src_id) != Some(&NodeType::Server) {
+            warn!(
+                "Server {}: Dropping server message from {}, which is not a known server",
+                self.id, src_id
+            );
+            return;
+        }
+
+        match message {
+            PeerMessage::Directory {
+                users,
+                reply,
+                binary,
+            } => self.handle_directory(src_id, users, reply, binary),
+            PeerMessage::Relay {
+                relay_id,
+                origin_server,
+                message_id,
+                sender_id,
+                recipient_id,
+                message,
+                visited,
+            } => self.handle_relay(
+                relay_id,
+                origin_server,
+                message_id,
+                sender_id,
+                recipient_id,
+                message,
+                visited,
+            ),
+            PeerMessage::RelayAck {
+                relay_id,
+                delivered,
+            } => self.handle_relay_ack(&relay_id, delivered),
+            PeerMessage::Replicate { messages } => self.handle_replicate(src_id, messages),
+        }
+    }
+
+    /// Relays a private message to the server on which its recipient is registered.
+    ///
+    /// ### Parameters
+    /// - `src_id: NodeId` – The local sender.
+    /// - `dest_id: NodeId` – The recipient, registered on `server_id`.
+    /// - `server_id: NodeId` – The server of the recipient.
+    /// - `message: &MessageBody` – The message.
+    /// - `db_message_id: Option<Uuid>` – The id of the stored message, marked as acked or failed
+    ///   when the `RelayAck` is received.
+    pub(crate) fn relay_private_message(
+        &mut self,
+        src_id: NodeId,
+        dest_id: NodeId,
+        server_id: NodeId,
+        message: &MessageBody,
+        db_message_id: Option<Uuid>,
+    ) {
+        let relay_id = Uuid::new_v4().to_string();
+        self.pending_relays.insert(
+            relay_id.clone(),
+            PendingRelay {
+                sender_id: src_id,
+                recipient_id: dest_id,
+                message: message.clone(),
+                db_message_id,
+                sent_at: Instant::now(),
+            },
+        );
+        self.seen_relays.insert(relay_id.clone(), Instant::now());
+
+        info!(
+            "Server {}: Relaying message from {} to {} through server {}",
+            self.id, src_id, dest_id, server_id
+        );
+        StatsManager::inc_messages_relayed(self.id);
+        let message_id = db_message_id.unwrap_or_else(Uuid::new_v4).to_string();
+        self.send_tracked_message(
+            server_id,
+            PeerMessage::Relay {
+                relay_id,
+                origin_server: self.id,
+                message_id,
+                sender_id: src_id,
+                recipient_id: dest_id,
+                message: message.clone(),
+                visited: vec![self.id],
+            },
+            None,
+        );
+        if let Some(db_message_id) = db_message_id {
+            self.set_delivery_state(db_message_id, DeliveryState::Sent);
+        }
+    }
+
+    /// Handles a private message relayed by another server
+    ///
+    /// ### Behavior
+    /// 1. Drops the messages already received, and the ones that went through this server.
+    /// 2. If the recipient is registered on this server, saves the message under the id given by the origin
+    ///    server unless its replica is already stored, and sends it to the recipient. The `RelayAck` is sent to
+    ///    the origin server once the session toward the recipient is acked or failed, see `complete_relay_delivery()`.
+    /// 3. Otherwise, if the recipient is registered on a server the message did not go through and the hop limit
+    ///    is not reached, relays it to that server.
+    /// 4. Otherwise sends a failed `RelayAck` to the origin server.
+    fn handle_relay(
+        &mut self,
+        relay_id: String,
+        origin_server: NodeId,
+        message_id: String,
+        sender_id: NodeId,
+        recipient_id: NodeId,
+        message: MessageBody,
+        mut visited: Vec<NodeId>,
+    ) {
+        if self.seen_relays.contains_key(&relay_id) || visited.contains(&self.id) {
+            info!(
+                "Server {}: Dropping relayed message {} already handled",
+                self.id, relay_id
+            );
+            return;
+        }
+        self.seen_relays.insert(relay_id.clone(), Instant::now());
+
+        if self.active_users.contains_key(&recipient_id) {
+            // A message that could not be stored is still tracked, under an id of its own
+            let db_message_id = self
+                .store_relayed_message(message_id, sender_id, recipient_id, &message)
+                .unwrap_or_else(Uuid::new_v4);
+            self.relay_deliveries
+                .insert(db_message_id, (origin_server, relay_id));
+            self.send_tracked_message(
+                recipient_id,
+                HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
+                    sender_id,
+                    message,
+                }),
+                Some(db_message_id),
+            );
+            return;
+        }
+
+        match self.remote_user_server(recipient_id) {
+            Some(server_id) if !visited.contains(&server_id) && visited.len() < MAX_RELAY_HOPS => {
+                info!(
+                    "Server {}: Forwarding relayed message {} to server {}",
+                    self.id, relay_id, server_id
+                );
+                visited.push(self.id);
+                StatsManager::inc_messages_relayed(self.id);
+                self.send_tracked_message(
+                    server_id,
+                    PeerMessage::Relay {
+                        relay_id,
+                        origin_server,
+                        message_id,
+                        sender_id,
+                        recipient_id,
+                        message,
+                        visited,
+                    },
+                    None,
+                );
+            }
+            _ => {
+                warn!(
+                    "Server {}: Cannot relay message {} to {}",
+                    self.id, relay_id, recipient_id
+                );
+                self.send_relay_ack(origin_server, relay_id, false);
+            }
+        }
+    }
+
+    /// Saves a relayed message under the id given by its origin server.
+    ///
+    /// The origin server replicates the message too, so it is only stored, and pushed to the UI,
+    /// if its replica did not arrive first.
+    ///
+    /// ### Returns
+    /// - `Some(Uuid)`: The id of the message, whether it was stored now or before.
+    /// - `None`: If the id is not valid or the message could not be stored.
+    fn store_relayed_message(
+        &mut self,
+        message_id: String,
+        sender_id: NodeId,
+        recipient_id: NodeId,
+        message: &MessageBody,
+    ) -> Option<Uuid> {
+        let db_message_id = Uuid::parse_str(&message_id).ok()?;
+        let db_manager = self.db_manager.as_ref().ok()?;
+        let db_message = DbMessage::from_body(message_id, sender_id, recipient_id, message);
+        match db_manager.insert_replica(&db_message) {
+            Ok(true) => self.send_db_message(db_message),
+            Ok(false) => {}
+            Err(err) => {
+                warn!(
+                    "Server {}: Cannot store relayed message {}: {}",
+                    self.id, db_message_id, err
+                );
+                return None;
+            }
+        }
+        Some(db_message_id)
+    }
+
+    /// Sends the `RelayAck` of a relayed message delivered to a local recipient, once the session carrying
+    /// it is acked or failed.
+    pub(crate) fn complete_relay_delivery(
+        &mut self,
+        db_message_id: Uuid,
+        delivery_state: DeliveryState,
+    ) {
+        if !matches!(delivery_state, DeliveryState::Acked | DeliveryState::Failed) {
+            return;
+        }
+        if let Some((origin_server, relay_id)) = self.relay_deliveries.remove(&db_message_id) {
+            self.send_relay_ack(
+                origin_server,
+                relay_id,
+                delivery_state == DeliveryState::Acked,
+            );
+        }
+    }
+
+    /// Handles the outcome of a relayed message, reporting a failure to its sender.
+    fn handle_relay_ack(&mut self, relay_id: &str, delivered: bool) {
+        let Some(relay) = self.pending_relays.remove(relay_id) else {
+            return;
+        };
+
+        if delivered {
+            if let Some(db_message_id) = relay.db_message_id {
+                self.set_delivery_state(db_message_id, DeliveryState::Acked);
+            }
+        } else {
+            self.complete_relay_failure(relay);
+        }
+    }
+
+    /// Fails a relayed message that could not reach the next server.
+    ///
+    /// The origin server reports the failure to the sender, a server in the middle of the relay
+    /// notifies the origin server with a failed `RelayAck`.
+    pub(crate) fn fail_relay(&mut self, relay_id: &str, origin_server: NodeId) {
+        if let Some(relay) = self.pending_relays.remove(relay_id) {
+            self.complete_relay_failure(relay);
+        } else if origin_server != self.id {
+            self.send_relay_ack(origin_server, relay_id.to_string(), false);
+        }
+    }
+
+    /// Fails the relayed messages whose `RelayAck` did not arrive in time and forgets
+    /// the ids of the relayed messages seen long ago.
+    pub(crate) fn expire_relays(&mut self) {
+        let expired: Vec<String> = self
+            .pending_relays
+            .iter()
+            .filter(|(_, relay)| relay.sent_at.elapsed() >= RELAY_ACK_TIMEOUT)
+            .map(|(relay_id, _)| relay_id.clone())
+            .collect();
+        for relay_id in expired {
+            if let Some(relay) = self.pending_relays.remove(&relay_id) {
+                warn!(
+                    "Server {}: No acknowledgement for relayed message {}",
+                    self.id, relay_id
+                );
+                self.complete_relay_failure(relay);
+            }
+        }
+
+        self.seen_relays
+            .retain(|_, seen_at| seen_at.elapsed() < SEEN_RELAY_RETENTION);
+    }
+
+    fn complete_relay_failure(&mut self, relay: PendingRelay) {
+        StatsManager::inc_relays_failed(self.id);
+        if let Some(db_message_id) = relay.db_message_id {
+            self.set_delivery_state(db_message_id, DeliveryState::Failed);
+        }
+        self.notify_sending_failure(
+            relay.recipient_id,
+            &HostMessage::FromServer(ServerToClientMessage::PrivateMessage {
+                sender_id: relay.sender_id,
+                message: relay.message,
+            }),
+        );
+    }
+
+    fn send_relay_ack(&mut self, origin_server: NodeId, relay_id: String, delivered: bool) {
+        self.send_tracked_message(
+            origin_server,
+            PeerMessage::RelayAck {
+                relay_id,
+                delivered,
+            },
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::peer::NetworkPayload;
+    use common_utils::MessageContent;
+
+    /// Returns the `RelayAck`s parked toward a server without a route
+    fn parked_relay_acks(server: &RustBustersServer, server_id: NodeId) -> Vec<bool> {
+        server
+            .unroutable
+            .get(&server_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, payload, _)| match payload {
+                NetworkPayload::Server(PeerMessage::RelayAck { delivered, .. }) => Some(*delivered),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn acknowledges_relays_once_delivered() {
+        let mut server = RustBustersServer::for_tests(1);
+        server.known_node_types.insert(5, NodeType::Server);
+        server.active_users.insert(3, "bob".to_string());
+
+        for (relay_id, delivery_state) in
+            [("a", DeliveryState::Acked), ("b", DeliveryState::Failed)]
+        {
+            let message_id = Uuid::new_v4();
+            server.handle_peer_message(
+                5,
+                PeerMessage::Relay {
+                    relay_id: relay_id.to_string(),
+                    origin_server: 5,
+                    message_id: message_id.to_string(),
+                    sender_id: 7,
+                    recipient_id: 3,
+                    message: MessageBody {
+                        sender_id: 7,
+                        content: MessageContent::Text("hello".to_string()),
+                        timestamp: "1700000000".to_string(),
+                    },
+                    visited: vec![5],
+                },
+            );
+            // The message waits for a route toward its recipient, the origin server is not acknowledged yet
+            assert!(server.relay_deliveries.contains_key(&message_id));
+
+            server.set_delivery_state(message_id, delivery_state);
+            assert!(server.relay_deliveries.is_empty());
+        }
+        assert_eq!(parked_relay_acks(&server, 5), vec![true, false]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::server::db::DbMessage;
+use crate::server::peer::PeerMessage;
+use crate::{RustBustersServer, StatsManager};
+use log::{info, warn};
+use wg_2024::network::NodeId;
+
+impl RustBustersServer {
+    /// Sends a message just saved in the database to the other servers, when replication is enabled.
+    ///
+    /// Servers that missed some messages are skipped: the message is sent to them by `sync_replicas()`
+    /// together with the others once they are reachable again.
+    pub(crate) fn replicate_message(&mut self, db_message: &DbMessage) {
+        if !self.replication.enabled {
+            return;
+        }
+
+        for server_id in self.peer_servers() {
+            if self.replication_backlog.contains_key(&server_id) {
+                continue;
+            }
+            StatsManager::inc_replication_batches_sent(self.id);
+            self.send_tracked_message(
+                server_id,
+                PeerMessage::Replicate {
+                    messages: vec![db_message.clone()],
+                },
+                None,
+            );
+        }
+    }
+
+    /// Records that some messages could not be replicated to a server.
+    ///
+    /// The server is brought up to date from the oldest missed message when it becomes reachable again.
+    pub(crate) fn record_missed_replicas(&mut self, server_id: NodeId, messages: &[DbMessage]) {
+        let Some(oldest) = messages.iter().map(DbMessage::timestamp).min() else {
+            return;
+        };
+
+        warn!(
+            "Server {}: Server {} missed {} replicated messages",
+            self.id,
+            server_id,
+            messages.len()
+        );
+        self.replication_backlog
+            .entry(server_id)
+            .and_modify(|since| *since = (*since).min(oldest))
+            .or_insert(oldest);
+    }
+
+    /// Brings the other servers up to date after a topology change.
+    ///
+    /// ### Behavior
+    /// - Servers found for the first time receive every stored message.
+    /// - Servers that missed some messages and are reachable again receive the messages
+    ///   stored since the oldest missed one.
+    ///
+    /// Messages are sent in batches of `batch_size` with their current delivery state; the receivers store
+    /// the ones they do not have and update the state of the others.
+    pub(crate) fn sync_replicas(&mut self) {
+        if !self.replication.enabled {
+            return;
+        }
+
+        for server_id in self.peer_servers() {
+            let since = if self.replication_peers.insert(server_id) {
+                Some(0)
+            } else if self.replication_backlog.contains_key(&server_id)
+                && self.get_route(server_id).is_some()
+            {
+                self.replication_backlog.remove(&server_id)
+            } else {
+                None
+            };
+
+            if let Some(since) = since {
+                self.push_replicas(server_id, since);
+            }
+        }
+    }
+
+    /// Handles messages replicated by another server, storing the ones not already present and updating the
+    /// delivery state of the others.
+    pub(crate) fn handle_replicate(&mut self, server_id: NodeId, messages: Vec<DbMessage>) {
+        let Ok(db_manager) = &self.db_manager else {
+            return;
+        };
+
+        let mut stored = Vec::new();
+        for db_message in messages {
+            match db_manager.upsert_replica(&db_message) {
+                Ok(true) => stored.push(db_message),
+                Ok(false) => {}
+                Err(err) => warn!(
+                    "Server {}: Cannot store message {} replicated by {}: {}",
+                    self.id,
+                    db_message.id(),
+                    server_id,
+                    err
+                ),
+            }
+        }
+
+        if !stored.is_empty() {
+            info!(
+                "Server {}: Stored or updated {} messages replicated by server {}",
+                self.id,
+                stored.len(),
+                server_id
+            );
+        }
+        for db_message in stored {
+            StatsManager::inc_replicas_stored(self.id);
+            self.send_db_message(db_message);
+        }
+    }
+
+    /// Sends to a server, in batches, the messages stored since the specified Unix timestamp.
+    fn push_replicas(&mut self, server_id: NodeId, since: i64) {
+        let batch_size = self.replication.batch_size.max(1);
+        let mut offset = 0;
+        loop {
+            let batch = match &self.db_manager {
+                Ok(db_manager) => match db_manager.get_since(since, offset, batch_size) {
+                    Ok(batch) => batch,
+                    Err(err) => {
+                        warn!(
+                            "Server {}: Cannot read the messages to replicate: {}",
+                            self.id, err
+                        );
+                        return;
+                    }
+                },
+                Err(_) => return,
+            };
+            if batch.is_empty() {
+                break;
+            }
+
+            info!(
+                "Server {}: Sending {} replicated messages to server {}",
+                self.id,
+                batch.len(),
+                server_id
+            );
+            offset += batch.len();
+            let last_batch = batch.len() < batch_size;
+            StatsManager::inc_replication_batches_sent(self.id);
+            self.send_tracked_message(server_id, PeerMessage::Replicate { messages: batch }, None);
+            if last_batch {
+                break;
+            }
+        }
+    }
+}
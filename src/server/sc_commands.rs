@@ -32,8 +32,8 @@ impl RustBustersServer {
     /// - Matches the `HostCommand` variant and performs the corresponding action:
     ///   - **SendRandomMessage**: Sends a random private message to a specified destination node.
     ///   - **DiscoverNetwork**: Initiates a network discovery process to learn about the network topology.
-    ///   - **AddSender**: Adds a sender to the `packet_send` map and triggers a network discovery.
-    ///   - **RemoveSender**: Removes a sender from the `packet_send` map and triggers a network discovery.
+    ///   - **AddSender**: Adds a sender to the `packet_send` map and requests an early network discovery.
+    ///   - **RemoveSender**: Removes a sender from the `packet_send` map and requests an early network discovery.
     ///   - **Stop**: Stops the server, sends a stop command to the `RustbusterServerController`, and marks the server as stopped.
     ///   - Other commands are ignored (default case).
     pub(crate) fn handle_command(&mut self, command: HostCommand) {
@@ -65,7 +65,8 @@ impl RustBustersServer {
                     .get_mut(&self.id)
                     .expect("Cannot unwrap topology")
                     .push(sender_id);
-                self.launch_network_discovery();
+                self.invalidate_route_cache();
+                self.request_discovery("sender added");
                 warn!("Server {}: Sender added", self.id);
             }
             HostCommand::RemoveSender(sender_id) => {
@@ -74,7 +75,8 @@ impl RustBustersServer {
                     .get_mut(&self.id)
                     .expect("Cannot unwrap topology")
                     .retain(|&id| id != sender_id);
-                self.launch_network_discovery();
+                self.invalidate_route_cache();
+                self.request_discovery("sender removed");
                 warn!("Server {}: Sender removed", self.id);
             }
             HostCommand::Stop => {
@@ -9,12 +9,14 @@ use wg_2024::network::NodeId;
 use wg_2024::network::SourceRoutingHeader;
 use wg_2024::packet::{Fragment, NodeType, Packet, PacketType};
 
-use crate::server::db::DbMessage;
+use crate::server::db::{DbMessage, Room, SearchFilter, SearchResult};
 use crate::utils::message::ActiveUsers;
-use crate::utils::message::{InternalMessage, ServerMessage, ServerMessages, WebSocketRequest};
+use crate::utils::message::{
+    InternalMessage, ServerMessage, ServerMessages, ServerRooms, ServerTopology, WebSocketRequest,
+};
 use common_utils::{HostMessage, ServerToClientMessage, User};
 
-use crossbeam_channel::{select_biased, unbounded, Receiver, RecvTimeoutError, Sender};
+use crossbeam_channel::{bounded, select_biased, unbounded, Receiver, RecvTimeoutError, Sender};
 use log::info;
 use std::collections::HashMap;
 use std::net::{TcpListener, TcpStream};
@@ -91,6 +93,15 @@ impl InternalChannelsManager {
             .send(InternalMessage::SendActiveUsers(active_users_message));
     }
 
+    pub fn send_rooms(server_id: NodeId, rooms: Vec<Room>) {
+        let mut channels = INTERNAL_CHANNELS.lock().unwrap();
+        let conn = channels
+            .get(&server_id)
+            .expect("Not connection found while sending rooms");
+        let rooms_message = ServerRooms::new(server_id, rooms);
+        conn.sender.send(InternalMessage::SendRooms(rooms_message));
+    }
+
     pub fn receive_and_forward_message(ws_stream: &mut WebSocket<TcpStream>) {
         let mut channels = INTERNAL_CHANNELS.lock().unwrap();
         for (&server_id, conn) in channels.iter() {
@@ -134,6 +145,13 @@ impl InternalChannelsManager {
                         .expect("Should be serializable")
                 );
             }
+            InternalMessage::SendRooms(server_rooms) => {
+                ws_message = format!(
+                    "{{\"serverId\":{},\"rooms\":{}}}",
+                    server_rooms.server_id,
+                    serde_json::to_string(&server_rooms.rooms).expect("Should be serializable")
+                );
+            }
             _ => {}
         }
         ws_message
@@ -152,6 +170,9 @@ impl InternalChannelsManager {
     }
 }
 
+// Maximum time the HTTP server waits for a Network Server to answer a request
+const SYNC_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
 // WebSocket Message Channels
 static WS_CHANNELS: LazyLock<Mutex<HashMap<NodeId, Sender<WebSocketRequest>>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
@@ -187,6 +208,71 @@ impl WSChannelsManager {
         channel.send(WebSocketRequest::GetActiveUsers);
     }
 
+    pub fn get_server_rooms(server_id: NodeId) {
+        // Send message to Internal channel of the specified Network Server
+        let ws_channels = WS_CHANNELS.lock().unwrap();
+        let channel = ws_channels
+            .get(&server_id)
+            .expect("No channel found while retrieving rooms");
+        channel.send(WebSocketRequest::GetRooms);
+    }
+
+    pub fn announce(
+        server_id: NodeId,
+        message: String,
+        recipients: Option<Vec<NodeId>>,
+    ) -> Option<Vec<NodeId>> {
+        let (reply, response) = bounded(1);
+        {
+            // Send message to Internal channel of the specified Network Server
+            let ws_channels = WS_CHANNELS.lock().unwrap();
+            let channel = ws_channels.get(&server_id)?;
+            channel
+                .send(WebSocketRequest::Announce {
+                    message,
+                    recipients,
+                    reply,
+                })
+                .ok()?;
+        }
+        // Wait for the Network Server to answer, without holding the lock
+        response.recv_timeout(SYNC_REQUEST_TIMEOUT).ok()
+    }
+
+    pub fn search_server_messages(
+        server_id: NodeId,
+        query: String,
+        filter: SearchFilter,
+    ) -> Option<Result<Vec<SearchResult>, String>> {
+        let (reply, response) = bounded(1);
+        {
+            // Send message to Internal channel of the specified Network Server
+            let ws_channels = WS_CHANNELS.lock().unwrap();
+            let channel = ws_channels.get(&server_id)?;
+            channel
+                .send(WebSocketRequest::SearchMessages {
+                    query,
+                    filter,
+                    reply,
+                })
+                .ok()?;
+        }
+        // Wait for the Network Server to answer, without holding the lock
+        response.recv_timeout(SYNC_REQUEST_TIMEOUT).ok()
+    }
+
+    pub fn get_server_topology(server_id: NodeId) -> Option<ServerTopology> {
+        let (reply, response) = bounded(1);
+        {
+            // Send message to Internal channel of the specified Network Server
+            let ws_channels = WS_CHANNELS.lock().unwrap();
+            let channel = ws_channels.get(&server_id)?;
+            channel.send(WebSocketRequest::GetTopology { reply }).ok()?;
+        }
+        // Wait for the Network Server to answer, without holding the lock
+        response.recv_timeout(SYNC_REQUEST_TIMEOUT).ok()
+    }
+
     pub fn add_channel(server_id: NodeId) -> Receiver<WebSocketRequest> {
         // Adds a channels channel for the specified server_id
         let (sender, receiver) = unbounded::<WebSocketRequest>();
@@ -28,6 +28,46 @@ pub struct Stats {
     acks_received: u64,
 
     nacks_received: u64,
+
+    fragments_retransmitted: u64,
+    sessions_failed: u64,
+
+    route_cache_hits: u64,
+    route_cache_misses: u64,
+
+    messages_queued: u64,
+    queued_messages_expired: u64,
+
+    offline_messages_stored: u64,
+    offline_messages_delivered: u64,
+
+    users_evicted: u64,
+
+    announcements_sent: u64,
+
+    messages_relayed: u64,
+    relays_failed: u64,
+
+    replication_batches_sent: u64,
+    replicas_stored: u64,
+
+    messages_compressed: u64,
+    fragments_saved: u64,
+
+    fec_sessions_sent: u64,
+    parity_fragments_sent: u64,
+    fec_drops_absorbed: u64,
+    messages_recovered: u64,
+
+    sessions_discarded: u64,
+    fragments_rejected: u64,
+    duplicate_fragments: u64,
+
+    flood_requests_ignored: u64,
+    links_expired: u64,
+
+    discoveries_deferred: u64,
+    discovery_interval_ms: u64,
 }
 
 // Setters
@@ -56,6 +96,46 @@ impl Stats {
             acks_received: 0,
 
             nacks_received: 0,
+
+            fragments_retransmitted: 0,
+            sessions_failed: 0,
+
+            route_cache_hits: 0,
+            route_cache_misses: 0,
+
+            messages_queued: 0,
+            queued_messages_expired: 0,
+
+            offline_messages_stored: 0,
+            offline_messages_delivered: 0,
+
+            users_evicted: 0,
+
+            announcements_sent: 0,
+
+            messages_relayed: 0,
+            relays_failed: 0,
+
+            replication_batches_sent: 0,
+            replicas_stored: 0,
+
+            messages_compressed: 0,
+            fragments_saved: 0,
+
+            fec_sessions_sent: 0,
+            parity_fragments_sent: 0,
+            fec_drops_absorbed: 0,
+            messages_recovered: 0,
+
+            sessions_discarded: 0,
+            fragments_rejected: 0,
+            duplicate_fragments: 0,
+
+            flood_requests_ignored: 0,
+            links_expired: 0,
+
+            discoveries_deferred: 0,
+            discovery_interval_ms: 0,
         }
     }
 }
@@ -112,6 +192,127 @@ impl Stats {
     pub fn inc_nacks_received(&mut self) {
         self.nacks_received += 1;
     }
+
+    // Retransmissions
+    pub fn inc_fragments_retransmitted(&mut self) {
+        self.fragments_retransmitted += 1;
+    }
+
+    pub fn inc_sessions_failed(&mut self) {
+        self.sessions_failed += 1;
+    }
+
+    // Route cache
+    pub fn inc_route_cache_hits(&mut self) {
+        self.route_cache_hits += 1;
+    }
+
+    pub fn inc_route_cache_misses(&mut self) {
+        self.route_cache_misses += 1;
+    }
+
+    // Outbound queue
+    pub fn inc_messages_queued(&mut self) {
+        self.messages_queued += 1;
+    }
+
+    pub fn inc_queued_messages_expired(&mut self) {
+        self.queued_messages_expired += 1;
+    }
+
+    // Mailbox
+    pub fn inc_offline_messages_stored(&mut self) {
+        self.offline_messages_stored += 1;
+    }
+
+    pub fn inc_offline_messages_delivered(&mut self) {
+        self.offline_messages_delivered += 1;
+    }
+
+    // Presence
+    pub fn inc_users_evicted(&mut self) {
+        self.users_evicted += 1;
+    }
+
+    // Announcements
+    pub fn inc_announcements_sent(&mut self) {
+        self.announcements_sent += 1;
+    }
+
+    // Relay
+    pub fn inc_messages_relayed(&mut self) {
+        self.messages_relayed += 1;
+    }
+
+    pub fn inc_relays_failed(&mut self) {
+        self.relays_failed += 1;
+    }
+
+    // Replication
+    pub fn inc_replication_batches_sent(&mut self) {
+        self.replication_batches_sent += 1;
+    }
+
+    pub fn inc_replicas_stored(&mut self) {
+        self.replicas_stored += 1;
+    }
+
+    // Compression
+    pub fn inc_messages_compressed(&mut self) {
+        self.messages_compressed += 1;
+    }
+
+    pub fn add_fragments_saved(&mut self, count: u64) {
+        self.fragments_saved += count;
+    }
+
+    // Forward error correction
+    pub fn inc_fec_sessions_sent(&mut self) {
+        self.fec_sessions_sent += 1;
+    }
+
+    pub fn add_parity_fragments_sent(&mut self, count: u64) {
+        self.parity_fragments_sent += count;
+    }
+
+    pub fn inc_fec_drops_absorbed(&mut self) {
+        self.fec_drops_absorbed += 1;
+    }
+
+    pub fn inc_messages_recovered(&mut self) {
+        self.messages_recovered += 1;
+    }
+
+    // Reassembly
+    pub fn inc_sessions_discarded(&mut self) {
+        self.sessions_discarded += 1;
+    }
+
+    pub fn inc_fragments_rejected(&mut self) {
+        self.fragments_rejected += 1;
+    }
+
+    pub fn inc_duplicate_fragments(&mut self) {
+        self.duplicate_fragments += 1;
+    }
+
+    // Topology
+    pub fn inc_flood_requests_ignored(&mut self) {
+        self.flood_requests_ignored += 1;
+    }
+
+    pub fn add_links_expired(&mut self, count: u64) {
+        self.links_expired += count;
+    }
+
+    // Discovery
+    pub fn inc_discoveries_deferred(&mut self) {
+        self.discoveries_deferred += 1;
+    }
+
+    pub fn set_discovery_interval_ms(&mut self, interval_ms: u64) {
+        self.discovery_interval_ms = interval_ms;
+    }
 }
 
 static STATS: LazyLock<Mutex<HashMap<NodeId, Stats>>> =
@@ -198,6 +399,181 @@ impl StatsManager {
         server_stats.inc_nacks_received();
     }
 
+    // Retransmissions
+    pub fn inc_fragments_retransmitted(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_fragments_retransmitted();
+    }
+
+    pub fn inc_sessions_failed(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_sessions_failed();
+    }
+
+    // Route cache
+    pub fn inc_route_cache_hits(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_route_cache_hits();
+    }
+
+    pub fn inc_route_cache_misses(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_route_cache_misses();
+    }
+
+    // Outbound queue
+    pub fn inc_messages_queued(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_messages_queued();
+    }
+
+    pub fn inc_queued_messages_expired(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_queued_messages_expired();
+    }
+
+    // Mailbox
+    pub fn inc_offline_messages_stored(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_offline_messages_stored();
+    }
+
+    pub fn inc_offline_messages_delivered(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_offline_messages_delivered();
+    }
+
+    // Presence
+    pub fn inc_users_evicted(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_users_evicted();
+    }
+
+    // Announcements
+    pub fn inc_announcements_sent(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_announcements_sent();
+    }
+
+    // Relay
+    pub fn inc_messages_relayed(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_messages_relayed();
+    }
+
+    pub fn inc_relays_failed(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_relays_failed();
+    }
+
+    // Replication
+    pub fn inc_replication_batches_sent(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_replication_batches_sent();
+    }
+
+    pub fn inc_replicas_stored(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_replicas_stored();
+    }
+
+    // Compression
+    pub fn inc_messages_compressed(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_messages_compressed();
+    }
+
+    pub fn add_fragments_saved(server_id: NodeId, count: u64) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.add_fragments_saved(count);
+    }
+
+    // Forward error correction
+    pub fn inc_fec_sessions_sent(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_fec_sessions_sent();
+    }
+
+    pub fn add_parity_fragments_sent(server_id: NodeId, count: u64) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.add_parity_fragments_sent(count);
+    }
+
+    pub fn inc_fec_drops_absorbed(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_fec_drops_absorbed();
+    }
+
+    pub fn inc_messages_recovered(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_messages_recovered();
+    }
+
+    // Reassembly
+    pub fn inc_sessions_discarded(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_sessions_discarded();
+    }
+
+    pub fn inc_fragments_rejected(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_fragments_rejected();
+    }
+
+    pub fn inc_duplicate_fragments(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_duplicate_fragments();
+    }
+
+    // Topology
+    pub fn inc_flood_requests_ignored(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_flood_requests_ignored();
+    }
+
+    pub fn add_links_expired(server_id: NodeId, count: u64) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.add_links_expired(count);
+    }
+
+    // Discovery
+    pub fn inc_discoveries_deferred(server_id: NodeId) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.inc_discoveries_deferred();
+    }
+
+    pub fn set_discovery_interval_ms(server_id: NodeId, interval_ms: u64) {
+        let mut stats = STATS.lock().unwrap();
+        let server_stats = stats.entry(server_id).or_insert_with(Stats::new);
+        server_stats.set_discovery_interval_ms(interval_ms);
+    }
+
     pub fn get_stats(server_id: NodeId) -> Stats {
         let stats = STATS.lock().unwrap();
         stats.get(&server_id).cloned().unwrap_or_default()
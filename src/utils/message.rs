@@ -1,18 +1,35 @@
-use crate::server::db::DbMessage;
+use crate::server::db::{DbMessage, Room, SearchFilter, SearchResult};
 use crate::state::Stats;
 use common_utils::User;
+use crossbeam_channel::Sender;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use wg_2024::network::NodeId;
 
 // This module defines the message types used for communication between the Network Listener and the WebSocket Server. These messages are exchanged via crossbeam channels to facilitate real-time updates during the simulation.
 
 /// WebSocket Messages
 /// This message is sent as a request from the HTTP server to the WebSocket Server through a crossbeam channel.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Requests carrying a `reply` sender are answered directly to the HTTP server instead of through the WebSocket Server.
+#[derive(Debug, Clone)]
 pub enum WebSocketRequest {
     GetStats,
     GetMessages,
     GetActiveUsers,
+    GetRooms,
+    Announce {
+        message: String,
+        recipients: Option<Vec<NodeId>>,
+        reply: Sender<Vec<NodeId>>,
+    },
+    SearchMessages {
+        query: String,
+        filter: SearchFilter,
+        reply: Sender<Result<Vec<SearchResult>, String>>,
+    },
+    GetTopology {
+        reply: Sender<ServerTopology>,
+    },
 }
 
 /// Internal Server Messages
@@ -22,6 +39,7 @@ pub enum InternalMessage {
     SendServerMessage(ServerMessage),
     SendServerMessages(ServerMessages),
     SendActiveUsers(ActiveUsers),
+    SendRooms(ServerRooms),
 }
 
 /// Server Message
@@ -71,3 +89,97 @@ impl ActiveUsers {
         }
     }
 }
+
+/// Server Rooms
+/// Wrapper for the group chat rooms on a specific server
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerRooms {
+    pub(crate) server_id: NodeId,
+    pub(crate) rooms: Vec<Room>,
+}
+
+impl ServerRooms {
+    pub(crate) fn new(server_id: NodeId, rooms: Vec<Room>) -> Self {
+        Self { server_id, rooms }
+    }
+}
+
+/// Server Topology
+/// The network as learned by a server through the network discovery
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerTopology {
+    pub(crate) server_id: NodeId,
+    pub(crate) nodes: Vec<TopologyNode>,
+    pub(crate) links: Vec<TopologyLink>,
+    pub(crate) routes: Vec<ClientRoute>,
+}
+
+/// A node of the network and its type ("Client", "Drone" or "Server")
+#[derive(Debug, Clone, Serialize)]
+pub struct TopologyNode {
+    pub(crate) id: NodeId,
+    pub(crate) node_type: String,
+}
+
+/// A link between two nodes, with the discovery round and the Unix timestamp of its last confirmation
+/// (missing for the links not confirmed by a FloodResponse yet)
+#[derive(Debug, Clone, Serialize)]
+pub struct TopologyLink {
+    pub(crate) from: NodeId,
+    pub(crate) to: NodeId,
+    pub(crate) last_confirmed_round: Option<u64>,
+    pub(crate) last_confirmed_at: Option<u64>,
+}
+
+/// The route currently used by the server to reach a client, if any
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientRoute {
+    pub(crate) client_id: NodeId,
+    pub(crate) route: Option<Vec<NodeId>>,
+}
+
+impl ServerTopology {
+    /// Returns the topology in the Graphviz DOT language.
+    ///
+    /// Servers are drawn as boxes and clients as diamonds; the links used by the routes to the clients are blue.
+    pub fn to_dot(&self) -> String {
+        let route_links: HashSet<(NodeId, NodeId)> = self
+            .routes
+            .iter()
+            .filter_map(|client_route| client_route.route.as_ref())
+            .flat_map(|route| {
+                route
+                    .windows(2)
+                    .map(|hop| (hop[0].min(hop[1]), hop[0].max(hop[1])))
+            })
+            .collect();
+
+        let mut dot = format!("graph server_{} {{\n", self.server_id);
+        for node in &self.nodes {
+            let shape = match node.node_type.as_str() {
+                "Server" => "box",
+                "Client" => "diamond",
+                _ => "ellipse",
+            };
+            let style = if node.id == self.server_id {
+                ", style=bold"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    {} [label=\"{} {}\", shape={}{}];\n",
+                node.id, node.node_type, node.id, shape, style
+            ));
+        }
+        for link in &self.links {
+            let color = if route_links.contains(&(link.from, link.to)) {
+                " [color=blue]"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    {} -- {}{};\n", link.from, link.to, color));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}